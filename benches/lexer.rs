@@ -0,0 +1,74 @@
+// times the lexer and the whole assembler on generated programs of growing
+// size, the time per line should stay flat when both are linear
+//
+// cargo +nightly bench
+
+extern crate jcpu_asm;
+
+use std::time::{Duration, Instant};
+
+use jcpu_asm::{Lexer, TokenId, assemble};
+
+
+// `lines` lines of labels, arithmetic, memory and conditional jumps
+fn program(lines: usize) -> String {
+	let mut source = String::from("# generated\n");
+
+	for i in 0..lines / 4 {
+		source.push_str(&format!("label{}\n", i));
+		source.push_str(&format!("\tr{} -> r{} + {}\n", i % 15, (i + 1) % 15, i));
+		source.push_str(&format!("\tm32(r{}) -> r{}\n", i % 15, (i + 2) % 15));
+		source.push_str(&format!("\tr15 -> label{} ? !(r{} = 0)\n", i / 2, i % 15));
+	}
+
+	source
+}
+
+// best of a few runs
+fn time<F: FnMut()>(mut f: F) -> Duration {
+	(0..5)
+		.map(|_| {
+			let start = Instant::now();
+			f();
+			start.elapsed()
+		})
+		.min()
+		.unwrap()
+}
+
+fn per_line(duration: Duration, lines: usize) -> f64 {
+	duration.as_secs_f64() * 1e9 / lines as f64
+}
+
+fn main() {
+	println!("{:>8}  {:>12}  {:>12}  {:>12}  {:>12}", "lines", "lex", "ns/line", "assemble", "ns/line");
+
+	for &lines in &[10_000, 20_000, 40_000, 80_000, 160_000] {
+		let source = program(lines);
+
+		let lex = time(|| {
+			let mut lexer = Lexer::new(source.clone(), 0);
+
+			loop {
+				match lexer.token() {
+					Ok(ref token) if token.id == TokenId::Eof => break,
+					Ok(_) => (),
+					Err(err) => panic!("{:?}", err)
+				}
+			}
+		});
+
+		let asm = time(|| {
+			assemble(&source).unwrap();
+		});
+
+		println!(
+			"{:>8}  {:>10.2}ms  {:>12.1}  {:>10.2}ms  {:>12.1}",
+			lines,
+			lex.as_secs_f64() * 1e3,
+			per_line(lex, lines),
+			asm.as_secs_f64() * 1e3,
+			per_line(asm, lines)
+		);
+	}
+}
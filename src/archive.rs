@@ -0,0 +1,181 @@
+use object::{Object, Writer, Reader};
+
+
+// JiftCPU object archive, version 1
+//
+// "JARC" version
+// member count, (name, object bytes)*
+// index count, (label, member)*
+const MAGIC: &[u8; 4] = b"JARC";
+const VERSION: u32 = 1;
+
+#[derive(Debug, Clone)]
+pub struct Archive {
+	// objects and their names
+	pub members: Vec<(String, Object)>,
+	// labels exported by each member
+	pub index: Vec<(String, usize)>
+}
+
+impl Archive {
+	pub fn new(members: Vec<(String, Object)>) -> Archive {
+		let mut index = vec![];
+
+		for (i, (_, object)) in members.iter().enumerate() {
+			for symbol in object.globals() {
+				index.push((symbol.name.clone(), i));
+			}
+		}
+
+		index.sort();
+
+		Archive { members, index }
+	}
+
+	// first member defining `label`
+	pub fn find(&self, label: &str) -> Option<usize> {
+		self.index
+			.iter()
+			.find(|entry| entry.0 == label)
+			.map(|entry| entry.1)
+	}
+
+	pub fn is_archive(bytes: &[u8]) -> bool {
+		bytes.len() >= 4 && &bytes[..4] == MAGIC
+	}
+
+	pub fn to_bytes(&self) -> Vec<u8> {
+		let mut writer = Writer { bytes: MAGIC.to_vec() };
+
+		writer.num(VERSION as usize);
+
+		writer.num(self.members.len());
+
+		for (name, object) in &self.members {
+			writer.string(name);
+			writer.blob(&object.to_bytes());
+		}
+
+		writer.num(self.index.len());
+
+		for (label, member) in &self.index {
+			writer.string(label);
+			writer.num(*member);
+		}
+
+		writer.bytes
+	}
+
+	// `None` if the bytes are not a valid archive
+	pub fn from_bytes(bytes: &[u8]) -> Option<Archive> {
+		if !Archive::is_archive(bytes) {
+			return None
+		}
+
+		let mut reader = Reader { bytes, pos: 4 };
+
+		if reader.num()? != VERSION as usize {
+			return None
+		}
+
+		let mut members = vec![];
+
+		for _ in 0..reader.num()? {
+			let name = reader.string()?;
+			let object = Object::from_bytes(reader.blob()?)?;
+
+			members.push((name, object));
+		}
+
+		let mut index = vec![];
+
+		for _ in 0..reader.num()? {
+			let label = reader.string()?;
+			let member = reader.num()?;
+
+			if member >= members.len() {
+				return None
+			}
+
+			index.push((label, member));
+		}
+
+		if reader.pos != bytes.len() {
+			return None
+		}
+
+		Some(Archive { members, index })
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use assembler::Assembler;
+
+	fn member(source: &str) -> Object {
+		let mut assembler = Assembler::new();
+		assembler.object = true;
+
+		Object::new(&assembler.assemble("-", String::from(source)).unwrap())
+	}
+
+	fn sample() -> Archive {
+		Archive::new(vec![
+			(String::from("a.o"), member(".global a, b\na\nb\n\tr0 -> 1\n")),
+			(String::from("c.o"), member(".global c\n.extern a\nc\n\tcall a\n"))
+		])
+	}
+
+	#[test]
+	fn round_trip() {
+		let bytes = sample().to_bytes();
+		let archive = Archive::from_bytes(&bytes).unwrap();
+
+		assert_eq!(archive.to_bytes(), bytes);
+		assert_eq!(archive.members.len(), 2);
+		assert_eq!(archive.members[1].0, "c.o");
+		assert_eq!(archive.index, sample().index);
+		assert_eq!(archive.find("b"), Some(0));
+		assert_eq!(archive.find("c"), Some(1));
+		assert_eq!(archive.find("d"), None);
+	}
+
+	#[test]
+	fn truncated() {
+		let bytes = sample().to_bytes();
+
+		for len in 0..bytes.len() {
+			assert!(Archive::from_bytes(&bytes[..len]).is_none());
+		}
+	}
+
+	#[test]
+	fn corrupt() {
+		let bytes = sample().to_bytes();
+
+		let mut magic = bytes.clone();
+		magic[0] = b'X';
+		assert!(Archive::from_bytes(&magic).is_none());
+
+		let mut version = bytes.clone();
+		version[4] = 2;
+		assert!(Archive::from_bytes(&version).is_none());
+
+		let mut trailing = bytes.clone();
+		trailing.push(0);
+		assert!(Archive::from_bytes(&trailing).is_none());
+
+		// an index entry naming a member that does not exist
+		let mut index = sample();
+		index.index[0].1 = 2;
+		assert!(Archive::from_bytes(&index.to_bytes()).is_none());
+
+		// a member that is not an object
+		let mut member = bytes.clone();
+		let start = bytes.windows(4).position(|window| window == b"JOBJ").unwrap();
+		member[start] = b'X';
+		assert!(Archive::from_bytes(&member).is_none());
+	}
+}
@@ -0,0 +1,154 @@
+use std::path::PathBuf;
+
+use loc::Loc;
+use error::{ErrorId, Error};
+use parser::parse_file;
+use node::{Program, Node};
+use source::Files;
+
+
+// assembled program and the files it was read from
+#[derive(Debug)]
+pub struct Assembly {
+	pub files: Files,
+	pub program: Program
+}
+
+impl Assembly {
+	pub fn binary(&self) -> &[u32] {
+		&self.program.binary
+	}
+}
+
+// parses and generates source files
+#[derive(Debug, Clone)]
+pub struct Assembler {
+	// every file read, `Loc::file` indexes it
+	pub files: Files,
+	// searched for `.include` files after the including file's directory
+	pub include_dirs: Vec<PathBuf>,
+	// labels given a value outside the source
+	pub defines: Vec<(String, u32)>,
+	// leave `.extern` labels undefined for the linker
+	pub object: bool
+}
+
+fn error_at(id: ErrorId, loc: Loc) -> Error {
+	Error {
+		id,
+		loc
+	}
+}
+
+// generates a parsed program
+pub fn generate(mut program: Program, defines: &[(String, u32)], object: bool) -> Result<Program, Vec<Error>> {
+	program.defines.extend(defines.iter().cloned());
+
+	if let Err(err) = program.gen() {
+		return Err(vec![err])
+	}
+
+	// objects leave `.extern` labels to the linker
+	let undefined = if object {
+		program.undefined()
+	} else {
+		program.unresolved()
+	};
+
+	let mut errors: Vec<Error> = undefined
+		.iter()
+		.map(|reloc| error_at(ErrorId::UndefinedLabel(reloc.label.clone()), reloc.loc.clone()))
+		.collect();
+
+	errors.extend(program
+		.undefined_globals()
+		.iter()
+		.map(|&(label, loc)| error_at(ErrorId::UndefinedGlobal(label.to_string()), loc.clone())));
+
+	if !errors.is_empty() {
+		return Err(errors)
+	}
+
+	Ok(program)
+}
+
+impl Default for Assembler {
+	fn default() -> Assembler {
+		Assembler::new()
+	}
+}
+
+impl Assembler {
+	pub fn new() -> Assembler {
+		Assembler {
+			files: Files::new(),
+			include_dirs: Vec::new(),
+			defines: Vec::new(),
+			object: false
+		}
+	}
+
+	// `name` is used in errors and to find files it includes
+	pub fn assemble(&mut self, name: &str, source: String) -> Result<Program, Vec<Error>> {
+		self.assemble_all(vec![(name.to_string(), source)])
+	}
+
+	// assembles named sources in order into one program sharing labels
+	// every file starts in the `text` section with default `.scratch`, `.link` and `.stack`
+	pub fn assemble_all(&mut self, sources: Vec<(String, String)>) -> Result<Program, Vec<Error>> {
+		let mut nodes = vec![];
+		let mut locs = vec![];
+		let mut errors = vec![];
+		let mut first = true;
+
+		for (name, source) in sources {
+			let file = self.files.add(name, source);
+
+			match parse_file(&mut self.files, file, &self.include_dirs) {
+				Err(err) => errors.push(err),
+				Ok(program) => {
+					if !first {
+						let resets = [
+							Node::Section(String::from("text")),
+							Node::Scratch(None),
+							Node::Link(14),
+							Node::Stack(13)
+						];
+
+						for node in resets {
+							nodes.push(node);
+							locs.push(Loc {
+								pos: 0,
+								col: 1,
+								line: 1,
+								file
+							});
+						}
+					}
+
+					nodes.extend(program.nodes);
+					locs.extend(program.locs);
+				}
+			}
+
+			first = false;
+		}
+
+		if !errors.is_empty() {
+			return Err(errors)
+		}
+
+		generate(Program::new(nodes, locs), &self.defines, self.object)
+	}
+}
+
+// assembles a single source without includes
+pub fn assemble(source: &str) -> Result<Assembly, Vec<Error>> {
+	let mut assembler = Assembler::new();
+	let program = assembler.assemble("-", source.to_string())?;
+
+	Ok(Assembly {
+		files: assembler.files,
+		program
+	})
+}
@@ -0,0 +1,414 @@
+use loc::Loc;
+use error::Error;
+use node::{Program, Node};
+use assembler::generate;
+
+
+// register written by an instruction
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Reg(pub u8);
+
+// value read by an instruction
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+	Reg(u8),
+	Imm(u32),
+	// address of a label, filled in by `Builder::build`
+	Label(String)
+}
+
+impl From<Reg> for Operand {
+	fn from(reg: Reg) -> Operand {
+		Operand::Reg(reg.0)
+	}
+}
+
+// panics unless `reg` is r0 to r15
+pub fn r(reg: u8) -> Reg {
+	assert!(reg <= 15, "invalid register r{}", reg);
+
+	Reg(reg)
+}
+
+pub fn imm(value: u32) -> Operand {
+	Operand::Imm(value)
+}
+
+pub fn label(name: &str) -> Operand {
+	Operand::Label(name.to_string())
+}
+
+// program counter
+pub const PC: Reg = Reg(15);
+
+impl Operand {
+	fn node(self) -> Node {
+		match self {
+			Operand::Reg(reg) => Node::Reg(reg),
+			Operand::Imm(value) => Node::Num(value as i32),
+			Operand::Label(name) => Node::Iden(name)
+		}
+	}
+}
+
+fn boxed<T: Into<Operand>>(operand: T) -> Box<Node> {
+	Box::new(operand.into().node())
+}
+
+// generates the same nodes the parser does, without source text
+//
+// `b.add(r(1), r(2), imm(3))` is `r1 -> r2 + 3`
+//
+// errors point at the call that added the node, counting from line 1
+pub struct Builder {
+	nodes: Vec<Node>,
+	locs: Vec<Loc>,
+	// condition register of the next instruction
+	cond: Option<u8>
+}
+
+impl Default for Builder {
+	fn default() -> Builder {
+		Builder::new()
+	}
+}
+
+impl Builder {
+	pub fn new() -> Builder {
+		Builder {
+			nodes: Vec::new(),
+			locs: Vec::new(),
+			cond: None
+		}
+	}
+
+	fn append(&mut self, node: Node) -> &mut Builder {
+		self.locs.push(Loc {
+			pos: 0,
+			col: 0,
+			line: self.nodes.len() + 1,
+			file: 0
+		});
+
+		self.nodes.push(node);
+		self
+	}
+
+	fn inst(&mut self, node: Node) -> &mut Builder {
+		let node = match self.cond.take() {
+			None => node,
+			Some(cond) => Node::Cond(
+				Box::new(node),
+				Box::new(Node::Not(Box::new(Node::Eql(Box::new(Node::Reg(cond)), Box::new(Node::Num(0))))))
+			)
+		};
+
+		self.append(node)
+	}
+
+	fn to(&mut self, dest: Reg, node: Node) -> &mut Builder {
+		self.inst(Node::To(Box::new(Node::Reg(dest.0)), Box::new(node)))
+	}
+
+	// makes the next instruction run only when `cond` is not 0
+	pub fn when(&mut self, cond: Reg) -> &mut Builder {
+		self.cond = Some(cond.0);
+		self
+	}
+
+	pub fn label(&mut self, name: &str) -> &mut Builder {
+		self.append(Node::Label(name.to_string()))
+	}
+
+	pub fn section(&mut self, name: &str) -> &mut Builder {
+		self.append(Node::Section(name.to_string()))
+	}
+
+	pub fn global(&mut self, name: &str) -> &mut Builder {
+		self.append(Node::Global(vec![name.to_string()]))
+	}
+
+	pub fn extern_label(&mut self, name: &str) -> &mut Builder {
+		self.append(Node::Extern(vec![name.to_string()]))
+	}
+
+	// data word, a register is an error
+	pub fn word<T: Into<Operand>>(&mut self, value: T) -> &mut Builder {
+		let node = value.into().node();
+		self.append(node)
+	}
+
+	pub fn mov<T: Into<Operand>>(&mut self, dest: Reg, a: T) -> &mut Builder {
+		let node = a.into().node();
+		self.to(dest, node)
+	}
+
+	pub fn not<T: Into<Operand>>(&mut self, dest: Reg, a: T) -> &mut Builder {
+		self.to(dest, Node::Not(boxed(a)))
+	}
+
+	pub fn neg<T: Into<Operand>>(&mut self, dest: Reg, a: T) -> &mut Builder {
+		self.to(dest, Node::Neg(boxed(a)))
+	}
+
+	// 2^32 / a
+	pub fn rep<T: Into<Operand>>(&mut self, dest: Reg, a: T) -> &mut Builder {
+		self.to(dest, Node::Rep(boxed(a)))
+	}
+
+	pub fn or<T: Into<Operand>, U: Into<Operand>>(&mut self, dest: Reg, a: T, b: U) -> &mut Builder {
+		self.to(dest, Node::Or(boxed(a), boxed(b)))
+	}
+
+	pub fn nor<T: Into<Operand>, U: Into<Operand>>(&mut self, dest: Reg, a: T, b: U) -> &mut Builder {
+		self.to(dest, Node::Not(Box::new(Node::Or(boxed(a), boxed(b)))))
+	}
+
+	pub fn and<T: Into<Operand>, U: Into<Operand>>(&mut self, dest: Reg, a: T, b: U) -> &mut Builder {
+		self.to(dest, Node::And(boxed(a), boxed(b)))
+	}
+
+	pub fn nand<T: Into<Operand>, U: Into<Operand>>(&mut self, dest: Reg, a: T, b: U) -> &mut Builder {
+		self.to(dest, Node::Not(Box::new(Node::And(boxed(a), boxed(b)))))
+	}
+
+	pub fn xor<T: Into<Operand>, U: Into<Operand>>(&mut self, dest: Reg, a: T, b: U) -> &mut Builder {
+		self.to(dest, Node::Xor(boxed(a), boxed(b)))
+	}
+
+	pub fn xnor<T: Into<Operand>, U: Into<Operand>>(&mut self, dest: Reg, a: T, b: U) -> &mut Builder {
+		self.to(dest, Node::Not(Box::new(Node::Xor(boxed(a), boxed(b)))))
+	}
+
+	pub fn add<T: Into<Operand>, U: Into<Operand>>(&mut self, dest: Reg, a: T, b: U) -> &mut Builder {
+		self.to(dest, Node::Add(boxed(a), boxed(b)))
+	}
+
+	pub fn sub<T: Into<Operand>, U: Into<Operand>>(&mut self, dest: Reg, a: T, b: U) -> &mut Builder {
+		self.to(dest, Node::Sub(boxed(a), boxed(b)))
+	}
+
+	pub fn lt<T: Into<Operand>, U: Into<Operand>>(&mut self, dest: Reg, a: T, b: U) -> &mut Builder {
+		self.to(dest, Node::Lt(boxed(a), boxed(b)))
+	}
+
+	pub fn sl<T: Into<Operand>, U: Into<Operand>>(&mut self, dest: Reg, a: T, b: U) -> &mut Builder {
+		self.to(dest, Node::Sl(boxed(a), boxed(b)))
+	}
+
+	pub fn sr<T: Into<Operand>, U: Into<Operand>>(&mut self, dest: Reg, a: T, b: U) -> &mut Builder {
+		self.to(dest, Node::Sr(boxed(a), boxed(b)))
+	}
+
+	pub fn mul<T: Into<Operand>, U: Into<Operand>>(&mut self, dest: Reg, a: T, b: U) -> &mut Builder {
+		self.to(dest, Node::Mul(boxed(a), boxed(b)))
+	}
+
+	pub fn div<T: Into<Operand>, U: Into<Operand>>(&mut self, dest: Reg, a: T, b: U) -> &mut Builder {
+		self.to(dest, Node::Div(boxed(a), boxed(b)))
+	}
+
+	pub fn load8<T: Into<Operand>>(&mut self, dest: Reg, addr: T) -> &mut Builder {
+		self.to(dest, Node::Mem8(boxed(addr)))
+	}
+
+	pub fn load16<T: Into<Operand>>(&mut self, dest: Reg, addr: T) -> &mut Builder {
+		self.to(dest, Node::Mem16(boxed(addr)))
+	}
+
+	pub fn load32<T: Into<Operand>>(&mut self, dest: Reg, addr: T) -> &mut Builder {
+		self.to(dest, Node::Mem32(boxed(addr)))
+	}
+
+	pub fn store8<T: Into<Operand>, U: Into<Operand>>(&mut self, addr: T, value: U) -> &mut Builder {
+		self.inst(Node::To(Box::new(Node::Mem8(boxed(addr))), boxed(value)))
+	}
+
+	pub fn store16<T: Into<Operand>, U: Into<Operand>>(&mut self, addr: T, value: U) -> &mut Builder {
+		self.inst(Node::To(Box::new(Node::Mem16(boxed(addr))), boxed(value)))
+	}
+
+	pub fn store32<T: Into<Operand>, U: Into<Operand>>(&mut self, addr: T, value: U) -> &mut Builder {
+		self.inst(Node::To(Box::new(Node::Mem32(boxed(addr))), boxed(value)))
+	}
+
+	pub fn jump<T: Into<Operand>>(&mut self, target: T) -> &mut Builder {
+		self.mov(PC, target)
+	}
+
+	// jumps when `cond` is not 0
+	pub fn jump_if<T: Into<Operand>>(&mut self, cond: Reg, target: T) -> &mut Builder {
+		self.when(cond).jump(target)
+	}
+
+	// saves the return address in the link register and jumps
+	pub fn call<T: Into<Operand>>(&mut self, target: T) -> &mut Builder {
+		self.inst(Node::Call(boxed(target)))
+	}
+
+	// jumps to the address in the link register
+	pub fn ret(&mut self) -> &mut Builder {
+		self.inst(Node::Ret)
+	}
+
+	pub fn nop(&mut self) -> &mut Builder {
+		self.inst(Node::Nop)
+	}
+
+	pub fn halt(&mut self) -> &mut Builder {
+		self.inst(Node::Halt)
+	}
+
+	// register used by following `call` and `ret`, `r14` by default
+	pub fn link(&mut self, reg: Reg) -> &mut Builder {
+		self.append(Node::Link(reg.0))
+	}
+
+	// register used by following `push` and `pop`, `r13` by default
+	pub fn stack(&mut self, reg: Reg) -> &mut Builder {
+		self.append(Node::Stack(reg.0))
+	}
+
+	pub fn push<T: Into<Operand>>(&mut self, value: T) -> &mut Builder {
+		self.inst(Node::Push(vec![value.into().node()]))
+	}
+
+	pub fn pop(&mut self, reg: Reg) -> &mut Builder {
+		self.inst(Node::Pop(vec![Node::Reg(reg.0)]))
+	}
+
+	// generates the program, filling in label addresses
+	pub fn build(&self) -> Result<Program, Vec<Error>> {
+		self.build_with(&[], false)
+	}
+
+	// `object` leaves `.extern` labels to the linker
+	pub fn build_with(&self, defines: &[(String, u32)], object: bool) -> Result<Program, Vec<Error>> {
+		let program = Program::new(self.nodes.clone(), self.locs.clone());
+
+		generate(program, defines, object)
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use assembler::assemble;
+	use emu::Cpu;
+
+	fn assert_same(builder: &Builder, source: &str) {
+		let program = builder.build().unwrap();
+		let assembly = assemble(source).unwrap();
+
+		assert_eq!(program.binary, assembly.binary());
+	}
+
+	fn run(builder: &Builder) -> Cpu {
+		let program = builder.build().unwrap();
+		let mut cpu = Cpu::new(&program.binary, 1024);
+
+		cpu.run(1000).unwrap();
+		cpu
+	}
+
+	#[test]
+	#[should_panic]
+	fn register_out_of_range() {
+		r(16);
+	}
+
+	#[test]
+	fn branches_match_source() {
+		assert_same(
+			Builder::new()
+				.label("loop")
+				.sub(r(1), r(1), imm(1))
+				.jump_if(r(1), label("loop"))
+				.jump(label("end"))
+				.jump(r(2))
+				.label("end")
+				.halt(),
+			"loop\n\tr1 -> r1 - 1\n\tjmp loop ? !(r1 = 0)\n\tjmp end\n\tjmp r2\nend\n\thalt\n"
+		);
+	}
+
+	#[test]
+	fn call_and_ret_match_source() {
+		assert_same(
+			Builder::new()
+				.call(label("f"))
+				.call(r(3))
+				.halt()
+				.label("f")
+				.link(r(12))
+				.ret()
+				.when(r(1))
+				.ret(),
+			"\tcall f\n\tcall r3\n\thalt\nf\n.link r12\n\tret\n\tret ? !(r1 = 0)\n"
+		);
+	}
+
+	#[test]
+	fn conditional_instructions_match_source() {
+		assert_same(
+			Builder::new()
+				.when(r(2))
+				.mov(r(1), imm(5))
+				.when(r(2))
+				.add(r(1), r(1), r(3))
+				.when(r(4))
+				.store32(r(5), r(1))
+				.when(r(4))
+				.call(label("f"))
+				.when(r(6))
+				.nop()
+				.label("f")
+				.when(r(6))
+				.halt(),
+			"\tr1 -> 5 ? !(r2 = 0)\n\tr1 -> r1 + r3 ? !(r2 = 0)\n\tm32(r5) -> r1 ? !(r4 = 0)\n\tcall f ? !(r4 = 0)\n\tnop ? !(r6 = 0)\nf\n\thalt ? !(r6 = 0)\n"
+		);
+	}
+
+	#[test]
+	fn when_applies_to_the_whole_push() {
+		for cond in [0, 1] {
+			let cpu = run(Builder::new()
+				.mov(r(13), imm(100))
+				.mov(r(1), imm(cond))
+				.when(r(1))
+				.push(imm(5))
+				.push(imm(7))
+				.halt());
+
+			assert_eq!(cpu.regs[13], 99 - cond);
+			assert_eq!(cpu.mem[cpu.regs[13] as usize], 7);
+		}
+	}
+
+	#[test]
+	fn when_applies_to_the_whole_pop() {
+		for cond in [0, 1] {
+			let cpu = run(Builder::new()
+				.mov(r(13), imm(100))
+				.push(imm(5))
+				.push(imm(7))
+				.mov(r(1), imm(cond))
+				.when(r(1))
+				.pop(r(2))
+				.pop(r(3))
+				.halt());
+
+			assert_eq!(cpu.regs[2], if cond == 0 { 0 } else { 7 });
+			assert_eq!(cpu.regs[3], if cond == 0 { 7 } else { 5 });
+			assert_eq!(cpu.regs[13], 99 + cond);
+		}
+	}
+
+	#[test]
+	fn when_cannot_test_a_popped_register() {
+		let errors = Builder::new().when(r(1)).pop(r(1)).build().unwrap_err();
+
+		assert_eq!(errors[0].loc.line, 1);
+	}
+}
@@ -0,0 +1,212 @@
+use std::path::PathBuf;
+
+use output::{Format, Endian, Options, max_record_len, valid_width};
+use script::parse_num;
+use diag::ErrorFormat;
+
+
+pub const USAGE: &str = "usage: jcpu-asm [COMMAND] [OPTIONS] [INPUTS]
+
+commands:
+    asm       assemble source files into one image (default)
+    disasm    disassemble a raw binary
+    run       run a source file or raw binary in the emulator
+    link      link objects and archives into an image
+    ar        collect objects into an archive
+
+options:
+    -o PATH             output file, `-` for stdout
+    -f, --format NAME   raw|text|ihex|srec|readmemh|readmemb|coe|mif
+    -l                  print a listing
+    -c                  write a relocatable object
+    -q, -v              print less or more
+    --error-format human|json
+    -D NAME[=VALUE]     define a label, 1 by default
+    -I DIR              search DIR for `.include` files
+    -T SCRIPT           linker script
+    -M                  print the files the output depends on as a Make rule
+    -MD                 also write the rule to the output path with `.d` added
+    -MF PATH            write the rule to PATH
+    -MT TARGET          target of the rule, the output path by default
+    -MP                 add an empty rule for every dependency
+    --map PATH          write a symbol map, JSON if PATH ends in `.json`
+    --endian little|big
+    --base ADDR         byte address of the first word
+    --record-len N      data bytes per ihex/srec record
+    --width 8|16|32     bits per memory word
+    --depth N           memory words to pad to
+    --steps N           instructions `run` executes before giving up
+    --mem N             words of emulator memory
+    -h, --help
+    -V, --version
+
+`-` as an input reads stdin.";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+	Asm,
+	Disasm,
+	Run,
+	Link,
+	Ar,
+	Help,
+	Version
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum Verbosity {
+	// errors only
+	Quiet,
+	// errors and warnings
+	Normal,
+	// also the parsed program and binary
+	Verbose
+}
+
+#[derive(Debug, Clone)]
+pub struct Args {
+	pub command: Command,
+	pub inputs: Vec<String>,
+	pub output: Option<String>,
+	pub listing: bool,
+	pub object: bool,
+	pub map: Option<String>,
+	pub script: Option<String>,
+	pub options: Options,
+	pub verbosity: Verbosity,
+	pub error_format: ErrorFormat,
+	pub defines: Vec<(String, u32)>,
+	pub include_dirs: Vec<PathBuf>,
+	// print the dependency rule instead of writing the output
+	pub deps_only: bool,
+	// write the dependency rule next to the output
+	pub deps: bool,
+	pub dep_file: Option<String>,
+	pub dep_target: Option<String>,
+	pub dep_phony: bool,
+	pub steps: usize,
+	pub mem: usize
+}
+
+impl Args {
+	pub fn new() -> Args {
+		Args {
+			command: Command::Asm,
+			inputs: Vec::new(),
+			output: None,
+			listing: false,
+			object: false,
+			map: None,
+			script: None,
+			options: Options::new(),
+			verbosity: Verbosity::Normal,
+			error_format: ErrorFormat::Human,
+			defines: Vec::new(),
+			include_dirs: Vec::new(),
+			deps_only: false,
+			deps: false,
+			dep_file: None,
+			dep_target: None,
+			dep_phony: false,
+			steps: 1_000_000,
+			mem: 65536
+		}
+	}
+}
+
+// `NAME` or `NAME=VALUE`
+fn parse_define(define: &str) -> Option<(String, u32)> {
+	let mut parts = define.splitn(2, '=');
+	let name = parts.next()?;
+
+	if name.is_empty() {
+		return None
+	}
+
+	let value = match parts.next() {
+		None => 1,
+		Some(value) => parse_num(value)?
+	};
+
+	Some((name.to_string(), value))
+}
+
+fn parse_value<T, F>(flag: &str, value: Option<String>, parse: F) -> Result<T, String>
+	where F: Fn(&str) -> Option<T> {
+	match value {
+		None => Err(format!("missing value for `{}`", flag)),
+		Some(value) => parse(&value).ok_or_else(|| format!("invalid value `{}` for `{}`", value, flag))
+	}
+}
+
+// errors come with the error format given so far
+pub fn parse_args<I: Iterator<Item = String>>(args: I) -> Result<Args, (String, ErrorFormat)> {
+	let mut parsed = Args::new();
+
+	match parse_into(&mut parsed, args) {
+		Err(message) => Err((message, parsed.error_format)),
+		Ok(()) => Ok(parsed)
+	}
+}
+
+fn parse_into<I: Iterator<Item = String>>(parsed: &mut Args, mut args: I) -> Result<(), String> {
+	let mut first = true;
+
+	while let Some(arg) = args.next() {
+		// the command may only come first
+		let is_first = first;
+		first = false;
+
+		match arg.as_ref() {
+			"asm" if is_first => parsed.command = Command::Asm,
+			"disasm" if is_first => parsed.command = Command::Disasm,
+			"run" if is_first => parsed.command = Command::Run,
+			"link" if is_first => parsed.command = Command::Link,
+			"ar" if is_first => parsed.command = Command::Ar,
+			"-h" | "--help" => parsed.command = Command::Help,
+			"-V" | "--version" => parsed.command = Command::Version,
+			"-l" => parsed.listing = true,
+			"-c" => parsed.object = true,
+			"-M" => parsed.deps_only = true,
+			"-MD" => parsed.deps = true,
+			"-MP" => parsed.dep_phony = true,
+			"-MF" => parsed.dep_file = Some(parse_value(&arg, args.next(), |path| Some(path.to_string()))?),
+			"-MT" => parsed.dep_target = Some(parse_value(&arg, args.next(), |target| Some(target.to_string()))?),
+			"-q" => parsed.verbosity = Verbosity::Quiet,
+			"-v" => parsed.verbosity = Verbosity::Verbose,
+			"-o" => parsed.output = Some(parse_value(&arg, args.next(), |path| Some(path.to_string()))?),
+			"--map" => parsed.map = Some(parse_value(&arg, args.next(), |path| Some(path.to_string()))?),
+			"-T" => parsed.script = Some(parse_value(&arg, args.next(), |path| Some(path.to_string()))?),
+			"-f" | "--format" => parsed.options.format = parse_value(&arg, args.next(), Format::from_name)?,
+			"--endian" => parsed.options.endian = parse_value(&arg, args.next(), Endian::from_name)?,
+			"--base" => parsed.options.base = parse_value(&arg, args.next(), parse_num)?,
+			"--record-len" => parsed.options.record_len = parse_value(&arg, args.next(), parse_num)? as usize,
+			"--width" => parsed.options.width = parse_value(&arg, args.next(), parse_num)? as usize,
+			"--depth" => parsed.options.depth = Some(parse_value(&arg, args.next(), parse_num)? as usize),
+			"--steps" => parsed.steps = parse_value(&arg, args.next(), parse_num)? as usize,
+			"--mem" => parsed.mem = parse_value(&arg, args.next(), parse_num)? as usize,
+			"--error-format" => parsed.error_format = parse_value(&arg, args.next(), ErrorFormat::from_name)?,
+			"-D" => parsed.defines.push(parse_value(&arg, args.next(), parse_define)?),
+			"-I" => parsed.include_dirs.push(parse_value(&arg, args.next(), |dir| Some(PathBuf::from(dir)))?),
+			// `--error-format=NAME`, `-DNAME=VALUE` and `-IDIR`
+			_ if arg.starts_with("--error-format=") =>
+				parsed.error_format = parse_value("--error-format", Some(arg[15..].to_string()), ErrorFormat::from_name)?,
+			_ if arg.starts_with("-D") => parsed.defines.push(parse_value("-D", Some(arg[2..].to_string()), parse_define)?),
+			_ if arg.starts_with("-I") => parsed.include_dirs.push(PathBuf::from(&arg[2..])),
+			_ if arg.starts_with('-') && arg != "-" => return Err(format!("unknown option `{}`", arg)),
+			_ => parsed.inputs.push(arg)
+		}
+	}
+
+	let options = &parsed.options;
+
+	if options.record_len == 0 || options.record_len > max_record_len(&options.format) {
+		return Err(String::from("invalid record length"))
+	}
+
+	if !valid_width(options.width) {
+		return Err(String::from("invalid word width"))
+	}
+
+	Ok(())
+}
@@ -1,4 +1,4 @@
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Opcode {
 	Mov = 0,
 	Not = 1,
@@ -31,7 +31,44 @@ pub enum Opcode {
 	Done = 28
 }
 
-#[derive(Debug)]
+impl Opcode {
+	pub fn from_u32(num: u32) -> Option<Opcode> {
+		match num {
+			0 => Some(Opcode::Mov),
+			1 => Some(Opcode::Not),
+			2 => Some(Opcode::Or),
+			3 => Some(Opcode::Nor),
+			4 => Some(Opcode::And),
+			5 => Some(Opcode::Nand),
+			6 => Some(Opcode::Xor),
+			7 => Some(Opcode::Xnor),
+			8 => Some(Opcode::Neg),
+			9 => Some(Opcode::Add),
+			10 => Some(Opcode::Sub),
+			11 => Some(Opcode::Lt),
+			12 => Some(Opcode::Nlt),
+			13 => Some(Opcode::Slt),
+			14 => Some(Opcode::Nslt),
+			15 => Some(Opcode::Sl),
+			16 => Some(Opcode::Sr),
+			17 => Some(Opcode::Ssl),
+			18 => Some(Opcode::Ssr),
+			19 => Some(Opcode::Rep),
+			20 => Some(Opcode::Mul),
+			21 => Some(Opcode::Div),
+			22 => Some(Opcode::Sto8),
+			23 => Some(Opcode::Sto16),
+			24 => Some(Opcode::Sto32),
+			25 => Some(Opcode::Lod8),
+			26 => Some(Opcode::Lod16),
+			27 => Some(Opcode::Lod32),
+			28 => Some(Opcode::Done),
+			_ => None
+		}
+	}
+}
+
+#[derive(Debug, Clone)]
 pub struct Inst {
 	pub opcode: Opcode,
 	pub ce: bool,
@@ -49,6 +86,12 @@ pub struct Inst {
 	pub imm0: u32
 }
 
+impl Default for Inst {
+	fn default() -> Inst {
+		Inst::new()
+	}
+}
+
 impl Inst {
 	pub fn new() -> Inst {
 		Inst {
@@ -106,4 +149,32 @@ impl Inst {
 
 		insts
 	}
+
+	// decode the first word of an instruction
+	// immediates are not part of the word and are left as 0
+	pub fn decode(inst: u32) -> Option<Inst> {
+		let opcode = Opcode::from_u32(inst >> 26)?;
+
+		Some(Inst {
+			opcode,
+			ce: (inst >> 25) & 1 == 1,
+			ci: (inst >> 24) & 1 == 1,
+			cond: ((inst >> 20) & 0b1111) as u8,
+			dest1: ((inst >> 16) & 0b1111) as u8,
+			dest0: ((inst >> 12) & 0b1111) as u8,
+			src1: ((inst >> 8) & 0b1111) as u8,
+			src0: ((inst >> 4) & 0b1111) as u8,
+			w1: (inst >> 3) & 1 == 1,
+			w0: (inst >> 2) & 1 == 1,
+			i1: (inst >> 1) & 1 == 1,
+			i0: inst & 1 == 1,
+			imm1: 0,
+			imm0: 0
+		})
+	}
+
+	// number of words the instruction occupies
+	pub fn size(&self) -> usize {
+		1 + self.i0 as usize + self.i1 as usize
+	}
 }
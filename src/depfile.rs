@@ -0,0 +1,57 @@
+// Make rule naming every file read to produce `target`
+//
+// out.bin: main.jasm \
+//   inc/defs.jasm
+//
+// `phony` adds an empty rule for every file after the first so deleted
+// includes do not break the build
+
+
+// spaces and `#` are escaped with `\`, `$` is doubled
+fn escape(path: &str) -> String {
+	let mut out = String::new();
+
+	for chr in path.chars() {
+		match chr {
+			' ' | '#' => {
+				out.push('\\');
+				out.push(chr);
+			},
+			'$' => out.push_str("$$"),
+			_ => out.push(chr)
+		}
+	}
+
+	out
+}
+
+// stdin is left out, files read twice are listed once
+pub fn depfile(target: &str, files: &[String], phony: bool) -> String {
+	let mut deps: Vec<&String> = vec![];
+
+	for file in files {
+		if file != "-" && !deps.contains(&file) {
+			deps.push(file);
+		}
+	}
+
+	let mut out = format!("{}:", escape(target));
+
+	for (i, dep) in deps.iter().enumerate() {
+		if i > 0 {
+			out.push_str(" \\\n ");
+		}
+
+		out.push_str(&format!(" {}", escape(dep)));
+	}
+
+	out.push('\n');
+
+	if phony {
+		for dep in deps.iter().skip(1) {
+			out.push_str(&format!("\n{}:\n", escape(dep)));
+		}
+	}
+
+	out
+}
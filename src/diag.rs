@@ -0,0 +1,134 @@
+use map::json_str;
+
+
+// process exit codes, 0 is success
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Exit {
+	// lexer and parser errors
+	Syntax = 1,
+	// invalid command line
+	Usage = 2,
+	// invalid instructions, undefined labels and link errors
+	Codegen = 3,
+	// files that cannot be read or written
+	Io = 4,
+	// the emulator stopped on a fault
+	Fault = 5
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ErrorFormat {
+	// `file:line:col: error: message`
+	Human,
+	// one JSON object per line
+	Json
+}
+
+impl ErrorFormat {
+	pub fn from_name(name: &str) -> Option<ErrorFormat> {
+		match name {
+			"human" => Some(ErrorFormat::Human),
+			"json" => Some(ErrorFormat::Json),
+			_ => None
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Severity {
+	Error,
+	Warning
+}
+
+// error or warning shown to the user
+#[derive(Debug, Clone)]
+pub struct Diag {
+	pub severity: Severity,
+	pub file: Option<String>,
+	// 0 when unknown
+	pub line: usize,
+	pub col: usize,
+	pub id: String,
+	pub message: String
+}
+
+impl Diag {
+	pub fn error(id: &str, message: String) -> Diag {
+		Diag {
+			severity: Severity::Error,
+			file: None,
+			line: 0,
+			col: 0,
+			id: id.to_string(),
+			message
+		}
+	}
+
+	pub fn warning(id: &str, message: String) -> Diag {
+		Diag {
+			severity: Severity::Warning,
+			..Diag::error(id, message)
+		}
+	}
+
+	pub fn at(self, file: &str, line: usize, col: usize) -> Diag {
+		Diag {
+			file: Some(file.to_string()),
+			line,
+			col,
+			..self
+		}
+	}
+
+	pub fn format(&self, format: &ErrorFormat) -> String {
+		let severity = match self.severity {
+			Severity::Error => "error",
+			Severity::Warning => "warning"
+		};
+
+		match *format {
+			ErrorFormat::Human => {
+				let mut out = String::new();
+
+				if let Some(ref file) = self.file {
+					out.push_str(&format!("{}:", file));
+
+					if self.line > 0 {
+						out.push_str(&format!("{}:", self.line));
+					}
+
+					if self.col > 0 {
+						out.push_str(&format!("{}:", self.col));
+					}
+
+					out.push(' ');
+				}
+
+				out.push_str(&format!("{}: {}", severity, self.message));
+				out
+			},
+			ErrorFormat::Json => {
+				let file = match self.file {
+					None => String::from("null"),
+					Some(ref file) => json_str(file)
+				};
+
+				// unknown positions are null
+				let num = |num: usize| match num {
+					0 => String::from("null"),
+					_ => num.to_string()
+				};
+
+				format!(
+					"{{\"severity\": \"{}\", \"file\": {}, \"line\": {}, \"col\": {}, \"id\": {}, \"message\": {}}}",
+					severity,
+					file,
+					num(self.line),
+					num(self.col),
+					json_str(&self.id),
+					json_str(&self.message)
+				)
+			}
+		}
+	}
+}
@@ -0,0 +1,121 @@
+use cpu::{Opcode, Inst};
+
+
+fn operand(reg: u8, imm: bool, value: u32) -> String {
+	if imm {
+		value.to_string()
+	} else {
+		format!("r{}", reg)
+	}
+}
+
+// source for an instruction, `None` if the syntax cannot express it
+pub fn inst_source(inst: &Inst) -> Option<String> {
+	let a = operand(inst.src0, inst.i0, inst.imm0);
+	let b = operand(inst.src1, inst.i1, inst.imm1);
+
+	let expr = match inst.opcode {
+		Opcode::Mov => a.clone(),
+		Opcode::Not => format!("!{}", a),
+		Opcode::Or => format!("{} | {}", a, b),
+		Opcode::Nor => format!("!({} | {})", a, b),
+		Opcode::And => format!("{} & {}", a, b),
+		Opcode::Nand => format!("!({} & {})", a, b),
+		Opcode::Xor => format!("{} ^ {}", a, b),
+		Opcode::Xnor => format!("!({} ^ {})", a, b),
+		Opcode::Neg => format!("-{}", a),
+		Opcode::Add => format!("{} + {}", a, b),
+		Opcode::Sub => format!("{} - {}", a, b),
+		Opcode::Lt => format!("{} < {}", a, b),
+		Opcode::Sl => format!("{} << {}", a, b),
+		Opcode::Sr => format!("{} >> {}", a, b),
+		Opcode::Rep => format!("/{}", a),
+		Opcode::Mul => format!("{} * {}", a, b),
+		Opcode::Div => format!("{} / {}", a, b),
+		Opcode::Lod8 => format!("m8({})", a),
+		Opcode::Lod16 => format!("m16({})", a),
+		Opcode::Lod32 => format!("m32({})", a),
+		Opcode::Sto8 | Opcode::Sto16 | Opcode::Sto32 | Opcode::Done => String::new(),
+		_ => return None
+	};
+
+	// no operands, as generated by `nop` and `halt`
+	let bare = !inst.w0 && !inst.w1 && !inst.i0 && !inst.i1 &&
+		inst.src0 == 0 && inst.src1 == 0 && inst.dest0 == 0 && inst.dest1 == 0;
+
+	let to = match inst.opcode {
+		Opcode::Mov if bare => String::from("nop"),
+		Opcode::Done if bare => String::from("halt"),
+		Opcode::Done => return None,
+		Opcode::Sto8 if !inst.w0 && !inst.w1 => format!("m8({}) -> {}", a, b),
+		Opcode::Sto16 if !inst.w0 && !inst.w1 => format!("m16({}) -> {}", a, b),
+		Opcode::Sto32 if !inst.w0 && !inst.w1 => format!("m32({}) -> {}", a, b),
+		_ if inst.w0 && !inst.w1 => format!("r{} -> {}", inst.dest0, expr),
+		_ => return None
+	};
+
+	match (inst.ce, inst.ci) {
+		(false, _) => Some(to),
+		(true, false) => Some(format!("{} ? !(r{} = 0)", to, inst.cond)),
+		(true, true) => None
+	}
+}
+
+// instruction at `addr` with its immediates, `None` if it is not one
+pub fn decode_at(binary: &[u32], addr: usize) -> Option<Inst> {
+	let mut inst = Inst::decode(binary[addr])?;
+
+	if addr + inst.size() > binary.len() {
+		return None
+	}
+
+	if inst.i0 {
+		inst.imm0 = binary[addr + 1];
+	}
+
+	if inst.i1 {
+		inst.imm1 = binary[addr + inst.size() - 1];
+	}
+
+	Some(inst)
+}
+
+fn fmt_words(addr: usize, words: &[u32]) -> String {
+	let words: Vec<String> = words.iter().map(|word| format!("{:08x}", word)).collect();
+
+	format!("{:04x}: {}", addr, words.join(" "))
+}
+
+// source that assembles back into `binary`
+// words that are not instructions become data words
+pub fn disasm(binary: &[u32]) -> String {
+	let mut out = String::new();
+	let mut addr = 0;
+
+	while addr < binary.len() {
+		match decode_at(binary, addr) {
+			Some(ref inst) => {
+				let words = &binary[addr..addr + inst.size()];
+
+				match inst_source(inst) {
+					Some(source) => out.push_str(&format!("\t{}  # {}\n", source, fmt_words(addr, words))),
+					None => {
+						out.push_str(&format!("# {:?}\n", inst.opcode));
+
+						for (i, word) in words.iter().enumerate() {
+							out.push_str(&format!("\t{}  # {}\n", word, fmt_words(addr + i, &[*word])));
+						}
+					}
+				}
+
+				addr += inst.size();
+			},
+			None => {
+				out.push_str(&format!("\t{}  # {}\n", binary[addr], fmt_words(addr, &binary[addr..addr + 1])));
+				addr += 1;
+			}
+		}
+	}
+
+	out
+}
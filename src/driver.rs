@@ -0,0 +1,420 @@
+use std::fs::File;
+use std::path::Path;
+use std::io::{self, prelude::*};
+
+use assembler::Assembler;
+use source::Files;
+use node::Program;
+use error::Error;
+use listing::listing;
+use map::{Symbol, map_text, map_json, symbols};
+use output::{output, words};
+use object::Object;
+use script::Script;
+use linker::{link, pull};
+use archive::Archive;
+use disasm::disasm;
+use emu::Cpu;
+use depfile::depfile;
+use diag::{Exit, ErrorFormat, Severity, Diag};
+use cli::{Command, Verbosity, Args, USAGE, parse_args};
+
+
+// prints a diagnostic, warnings are hidden by `-q`
+fn report(args: &Args, diag: Diag) {
+	if diag.severity == Severity::Warning && args.verbosity == Verbosity::Quiet {
+		return
+	}
+
+	eprintln!("{}", diag.format(&args.error_format));
+}
+
+// reads a file, `-` is stdin
+fn read_input(args: &Args, path: &str) -> Result<Vec<u8>, Exit> {
+	let mut bytes = vec![];
+
+	let result = if path == "-" {
+		io::stdin().read_to_end(&mut bytes)
+	} else {
+		File::open(path).and_then(|mut file| file.read_to_end(&mut bytes))
+	};
+
+	match result {
+		Err(err) => {
+			report(args, Diag::error("Io", format!("failed to read {}: {}", path, err)).at(path, 0, 0));
+			Err(Exit::Io)
+		},
+		Ok(_) => Ok(bytes)
+	}
+}
+
+fn read_source(args: &Args, path: &str) -> Result<String, Exit> {
+	match String::from_utf8(read_input(args, path)?) {
+		Err(_) => {
+			report(args, Diag::error("Io", format!("{} is not valid UTF-8", path)).at(path, 0, 0));
+			Err(Exit::Io)
+		},
+		Ok(source) => Ok(source)
+	}
+}
+
+// writes a file, `-` is stdout
+fn write_output(args: &Args, path: &str, bytes: &[u8]) -> Result<(), Exit> {
+	let result = if path == "-" {
+		io::stdout().write_all(bytes)
+	} else {
+		File::create(path).and_then(|mut file| file.write_all(bytes))
+	};
+
+	match result {
+		Err(err) => {
+			report(args, Diag::error("Io", format!("failed to write {}: {}", path, err)).at(path, 0, 0));
+			Err(Exit::Io)
+		},
+		Ok(_) => Ok(())
+	}
+}
+
+// writes the symbol map, JSON if the path ends in `.json`
+fn write_map(args: &Args, path: &str, symbols: &[Symbol], entry: Option<usize>) -> Result<(), Exit> {
+	let map = if path.ends_with(".json") {
+		map_json(symbols, entry)
+	} else {
+		map_text(symbols, entry)
+	};
+
+	write_output(args, path, map.as_bytes())
+}
+
+fn usage_error(args: &Args, message: &str) -> Exit {
+	report(args, Diag::error("Usage", message.to_string()));
+	Exit::Usage
+}
+
+fn single_input(args: &Args) -> Result<&str, Exit> {
+	match args.inputs.as_slice() {
+		[input] => Ok(input),
+		[] => Err(usage_error(args, "no input file")),
+		_ => Err(usage_error(args, "expected one input file"))
+	}
+}
+
+fn output_path(args: &Args) -> Result<&str, Exit> {
+	match args.output {
+		None => Err(usage_error(args, "no output file, use `-o PATH`")),
+		Some(ref path) => Ok(path)
+	}
+}
+
+fn error_diag(files: &Files, err: &Error) -> Diag {
+	Diag::error(err.id.name(), err.id.message()).at(&files.names[err.loc.file], err.loc.line, err.loc.col)
+}
+
+// parses and generates source files into one program, reporting any errors
+fn assemble(args: &Args, paths: &[String]) -> Result<(Files, Program), Exit> {
+	let mut sources = vec![];
+
+	for path in paths {
+		sources.push((path.clone(), read_source(args, path)?));
+	}
+
+	let mut assembler = Assembler::new();
+	assembler.include_dirs = args.include_dirs.clone();
+	assembler.defines = args.defines.clone();
+	assembler.object = args.object;
+
+	let program = match assembler.assemble_all(sources) {
+		Err(errors) => {
+			for err in &errors {
+				report(args, error_diag(&assembler.files, err));
+			}
+
+			return Err(errors[0].id.exit())
+		},
+		Ok(program) => program
+	};
+
+	if args.verbosity == Verbosity::Verbose {
+		eprintln!("{:?}", program);
+
+		for inst in &program.binary {
+			eprintln!("{:032b}", inst);
+		}
+	}
+
+	for (label, loc) in program.unused_externs() {
+		let diag = Diag::warning("UnusedExtern", format!("extern label `{}` is never used", label));
+
+		report(args, diag.at(&assembler.files.names[loc.file], loc.line, loc.col));
+	}
+
+	Ok((assembler.files, program))
+}
+
+// writes the Make rule for `-M` and `-MD`
+fn write_deps(args: &Args, files: &[String]) -> Result<(), Exit> {
+	if !args.deps_only && !args.deps {
+		return Ok(())
+	}
+
+	// without `-o` the target is named after the first input
+	let target = match (&args.dep_target, &args.output) {
+		(Some(target), _) => target.clone(),
+		(None, Some(output)) if output != "-" => output.clone(),
+		_ => match args.inputs.first() {
+			Some(input) if input != "-" => match Path::new(input).file_stem() {
+				Some(stem) => format!("{}.{}", stem.to_string_lossy(), if args.object { "o" } else { "bin" }),
+				None => String::from("-")
+			},
+			_ => String::from("-")
+		}
+	};
+
+	let path = match args.dep_file {
+		Some(ref path) => path.clone(),
+		None if args.deps_only => String::from("-"),
+		// `out.bin` writes `out.d`
+		None => Path::new(&target).with_extension("d").to_string_lossy().into_owned()
+	};
+
+	write_output(args, &path, depfile(&target, files, args.dep_phony).as_bytes())
+}
+
+fn run_asm(args: &Args) -> Result<(), Exit> {
+	if args.inputs.is_empty() {
+		return Err(usage_error(args, "no input file"))
+	}
+
+	if args.output.is_none() && !args.listing && args.map.is_none() && !args.deps_only {
+		output_path(args)?;
+	}
+
+	let (files, program) = assemble(args, &args.inputs)?;
+
+	write_deps(args, &files.names)?;
+
+	// `-M` only prints the dependencies
+	if args.deps_only {
+		return Ok(())
+	}
+
+	if args.listing {
+		print!("{}", listing(&files, &program));
+	}
+
+	if let Some(ref path) = args.map {
+		write_map(args, path, &symbols(&program), None)?;
+	}
+
+	match args.output {
+		None => Ok(()),
+		Some(ref path) => if args.object {
+			write_output(args, path, &Object::new(&program).to_bytes())
+		} else {
+			match output(&[(0, program.binary.clone())], &args.options) {
+				Err(err) => {
+					report(args, Diag::error(err.id(), err.message()));
+					Err(Exit::Codegen)
+				},
+				Ok(data) => write_output(args, path, &data)
+			}
+		}
+	}
+}
+
+fn read_binary(args: &Args, path: &str) -> Result<Vec<u32>, Exit> {
+	let bytes = read_input(args, path)?;
+
+	match words(&bytes, &args.options.endian) {
+		None => {
+			report(args, Diag::error("Io", format!("{} is not a whole number of words", path)).at(path, 0, 0));
+			Err(Exit::Io)
+		},
+		Some(binary) => Ok(binary)
+	}
+}
+
+fn run_disasm(args: &Args) -> Result<(), Exit> {
+	let binary = read_binary(args, single_input(args)?)?;
+
+	let path = args.output.as_ref().map(|path| path.as_ref()).unwrap_or("-");
+
+	write_output(args, path, disasm(&binary).as_bytes())
+}
+
+fn run_emu(args: &Args) -> Result<(), Exit> {
+	let path = single_input(args)?;
+
+	// sources are assembled first
+	let binary = if path == "-" || path.ends_with(".jasm") {
+		assemble(args, &args.inputs)?.1.binary
+	} else {
+		read_binary(args, path)?
+	};
+
+	let mut cpu = Cpu::new(&binary, args.mem);
+	let result = cpu.run(args.steps);
+
+	if args.verbosity > Verbosity::Quiet {
+		for (i, reg) in cpu.regs.iter().enumerate() {
+			println!("r{:<2} {:08x} {}", i, reg, reg);
+		}
+	}
+
+	if args.verbosity == Verbosity::Verbose {
+		println!("{} steps", cpu.steps);
+	}
+
+	match result {
+		Err(fault) => {
+			report(args, Diag::error(fault.id(), fault.message()));
+			Err(Exit::Fault)
+		},
+		Ok(_) => Ok(())
+	}
+}
+
+fn run_link(args: &Args) -> Result<(), Exit> {
+	if args.inputs.is_empty() {
+		return Err(usage_error(args, "no input file"))
+	}
+
+	let output_path = output_path(args)?;
+
+	let script = match args.script {
+		None => Script::new(),
+		Some(ref path) => match Script::parse(&read_source(args, path)?) {
+			Err(err) => {
+				let diag = Diag::error(err.id.name(), err.id.message());
+
+				report(args, diag.at(path, err.loc.line, 0));
+				return Err(Exit::Syntax)
+			},
+			Ok(script) => script
+		}
+	};
+
+	let mut loaded = vec![];
+	let mut archives = vec![];
+
+	for path in &args.inputs {
+		let bytes = read_input(args, path)?;
+
+		if Archive::is_archive(&bytes) {
+			match Archive::from_bytes(&bytes) {
+				None => {
+					report(args, Diag::error("InvalidArchive", format!("{} is not a valid archive", path)).at(path, 0, 0));
+					return Err(Exit::Io)
+				},
+				Some(archive) => archives.push((path.clone(), archive))
+			}
+		} else {
+			match Object::from_bytes(&bytes) {
+				None => {
+					report(args, Diag::error("InvalidObject", format!("{} is not a valid object file", path)).at(path, 0, 0));
+					return Err(Exit::Io)
+				},
+				Some(object) => loaded.push((path.clone(), object))
+			}
+		}
+	}
+
+	let mut read = args.inputs.clone();
+	read.extend(args.script.iter().cloned());
+
+	write_deps(args, &read)?;
+
+	if args.deps_only {
+		return Ok(())
+	}
+
+	// only members defining otherwise undefined labels are linked
+	let loaded = pull(loaded, &archives);
+
+	match link(&loaded, &script) {
+		Err(errors) => {
+			for err in errors {
+				report(args, Diag::error(err.id(), err.message()));
+			}
+
+			Err(Exit::Codegen)
+		},
+		Ok(image) => {
+			if let Some(ref path) = args.map {
+				write_map(args, path, &image.symbols, image.entry)?;
+			}
+
+			match output(&image.segments, &args.options) {
+				Err(err) => {
+					report(args, Diag::error(err.id(), err.message()));
+					Err(Exit::Codegen)
+				},
+				Ok(data) => write_output(args, output_path, &data)
+			}
+		}
+	}
+}
+
+fn run_ar(args: &Args) -> Result<(), Exit> {
+	let output_path = output_path(args)?;
+
+	let mut members = vec![];
+
+	for path in &args.inputs {
+		let bytes = read_input(args, path)?;
+
+		match Object::from_bytes(&bytes) {
+			None => {
+				report(args, Diag::error("InvalidObject", format!("{} is not a valid object file", path)).at(path, 0, 0));
+				return Err(Exit::Io)
+			},
+			Some(object) => {
+				let name = Path::new(path)
+					.file_name()
+					.map(|name| name.to_string_lossy().into_owned())
+					.unwrap_or_else(|| path.clone());
+
+				members.push((name, object));
+			}
+		}
+	}
+
+	write_output(args, output_path, &Archive::new(members).to_bytes())
+}
+
+// runs the command line without the program name, returns the exit code
+pub fn run_cli<I: Iterator<Item = String>>(args: I) -> i32 {
+	let args = match parse_args(args) {
+		Err((message, error_format)) => {
+			eprintln!("{}", Diag::error("Usage", message).format(&error_format));
+
+			if error_format == ErrorFormat::Human {
+				eprintln!("{}", USAGE);
+			}
+
+			return Exit::Usage as i32
+		},
+		Ok(args) => args
+	};
+
+	let result = match args.command {
+		Command::Help => {
+			println!("{}", USAGE);
+			Ok(())
+		},
+		Command::Version => {
+			println!("jcpu-asm {}", env!("CARGO_PKG_VERSION"));
+			Ok(())
+		},
+		Command::Asm => run_asm(&args),
+		Command::Disasm => run_disasm(&args),
+		Command::Run => run_emu(&args),
+		Command::Link => run_link(&args),
+		Command::Ar => run_ar(&args)
+	};
+
+	match result {
+		Err(exit) => exit as i32,
+		Ok(()) => 0
+	}
+}
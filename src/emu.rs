@@ -0,0 +1,205 @@
+use cpu::{Opcode, Inst};
+
+
+const PC: usize = 15;
+
+#[derive(Debug, Clone)]
+pub enum Fault {
+	// address of the word that is not an instruction
+	InvalidInst(usize),
+	// address outside of memory
+	OutOfRange(usize),
+	// address of the instruction dividing
+	DivZero(usize),
+	// step limit reached
+	Timeout
+}
+
+impl Fault {
+	pub fn id(&self) -> &'static str {
+		match *self {
+			Fault::InvalidInst(..) => "InvalidInst",
+			Fault::OutOfRange(..) => "OutOfRange",
+			Fault::DivZero(..) => "DivZero",
+			Fault::Timeout => "Timeout"
+		}
+	}
+
+	pub fn message(&self) -> String {
+		match *self {
+			Fault::InvalidInst(addr) => format!("invalid instruction at {:04x}", addr),
+			Fault::OutOfRange(addr) => format!("address {:04x} is outside of memory", addr),
+			Fault::DivZero(addr) => format!("division by zero at {:04x}", addr),
+			Fault::Timeout => String::from("step limit reached")
+		}
+	}
+}
+
+// word addressed JiftCPU
+pub struct Cpu {
+	pub regs: [u32; 16],
+	pub mem: Vec<u32>,
+	// end of the loaded program, reaching it halts
+	pub end: usize,
+	pub steps: usize
+}
+
+impl Cpu {
+	// `binary` is loaded at address 0
+	pub fn new(binary: &[u32], mem_len: usize) -> Cpu {
+		let mut mem = vec![0; mem_len.max(binary.len())];
+		mem[..binary.len()].copy_from_slice(binary);
+
+		Cpu {
+			regs: [0; 16],
+			mem,
+			end: binary.len(),
+			steps: 0
+		}
+	}
+
+	fn load(&self, addr: u32) -> Result<u32, Fault> {
+		self.mem.get(addr as usize).cloned().ok_or(Fault::OutOfRange(addr as usize))
+	}
+
+	fn store(&mut self, addr: u32, value: u32) -> Result<(), Fault> {
+		match self.mem.get_mut(addr as usize) {
+			None => Err(Fault::OutOfRange(addr as usize)),
+			Some(word) => {
+				*word = value;
+				Ok(())
+			}
+		}
+	}
+
+	// runs one instruction, false once halted
+	pub fn step(&mut self) -> Result<bool, Fault> {
+		let pc = self.regs[PC] as usize;
+
+		if pc >= self.end {
+			return Ok(false)
+		}
+
+		let mut inst = Inst::decode(self.load(pc as u32)?).ok_or(Fault::InvalidInst(pc))?;
+
+		if inst.i0 {
+			inst.imm0 = self.load(pc as u32 + 1)?;
+		}
+
+		if inst.i1 {
+			inst.imm1 = self.load((pc + inst.size() - 1) as u32)?;
+		}
+
+		self.regs[PC] = (pc + inst.size()) as u32;
+		self.steps += 1;
+
+		if inst.ce && (self.regs[inst.cond as usize] != 0) == inst.ci {
+			return Ok(true)
+		}
+
+		let a = if inst.i0 { inst.imm0 } else { self.regs[inst.src0 as usize] };
+		let b = if inst.i1 { inst.imm1 } else { self.regs[inst.src1 as usize] };
+
+		// results for dest0 and dest1
+		let (x, y) = match inst.opcode {
+			Opcode::Mov => (a, b),
+			Opcode::Not => (!a, !a),
+			Opcode::Or => (a | b, a | b),
+			Opcode::Nor => (!(a | b), !(a | b)),
+			Opcode::And => (a & b, a & b),
+			Opcode::Nand => (!(a & b), !(a & b)),
+			Opcode::Xor => (a ^ b, a ^ b),
+			Opcode::Xnor => (!(a ^ b), !(a ^ b)),
+			Opcode::Neg => (a.wrapping_neg(), a.wrapping_neg()),
+			Opcode::Add => (a.wrapping_add(b), a.wrapping_add(b)),
+			Opcode::Sub => (a.wrapping_sub(b), a.wrapping_sub(b)),
+			Opcode::Lt => ((a < b) as u32, (a < b) as u32),
+			Opcode::Nlt => ((a >= b) as u32, (a >= b) as u32),
+			Opcode::Slt => {
+				let lt = ((a as i32) < (b as i32)) as u32;
+				(lt, lt)
+			},
+			Opcode::Nslt => {
+				let nlt = ((a as i32) >= (b as i32)) as u32;
+				(nlt, nlt)
+			},
+			Opcode::Sl => (a.wrapping_shl(b), a.wrapping_shl(b)),
+			Opcode::Sr => (a.wrapping_shr(b), a.wrapping_shr(b)),
+			Opcode::Ssl => ((a as i32).wrapping_shl(b) as u32, (a as i32).wrapping_shl(b) as u32),
+			Opcode::Ssr => ((a as i32).wrapping_shr(b) as u32, (a as i32).wrapping_shr(b) as u32),
+			// 2^32 / a, saturating
+			Opcode::Rep => {
+				let rep = match a {
+					0 | 1 => u32::MAX,
+					_ => ((1u64 << 32) / a as u64) as u32
+				};
+				(rep, rep)
+			},
+			// low and high words
+			Opcode::Mul => {
+				let product = a as u64 * b as u64;
+				(product as u32, (product >> 32) as u32)
+			},
+			// quotient and remainder
+			Opcode::Div => {
+				if b == 0 {
+					return Err(Fault::DivZero(pc))
+				}
+				(a / b, a % b)
+			},
+			Opcode::Sto8 => {
+				let word = self.load(a)?;
+				self.store(a, (word & !0xff) | (b & 0xff))?;
+				return Ok(true)
+			},
+			Opcode::Sto16 => {
+				let word = self.load(a)?;
+				self.store(a, (word & !0xffff) | (b & 0xffff))?;
+				return Ok(true)
+			},
+			Opcode::Sto32 => {
+				self.store(a, b)?;
+				return Ok(true)
+			},
+			Opcode::Lod8 => {
+				let word = self.load(a)? & 0xff;
+				(word, word)
+			},
+			Opcode::Lod16 => {
+				let word = self.load(a)? & 0xffff;
+				(word, word)
+			},
+			Opcode::Lod32 => {
+				let word = self.load(a)?;
+				(word, word)
+			},
+			Opcode::Done => return Ok(false)
+		};
+
+		if inst.w0 {
+			self.regs[inst.dest0 as usize] = x;
+		}
+
+		if inst.w1 {
+			self.regs[inst.dest1 as usize] = y;
+		}
+
+		// an unconditional jump to itself never leaves
+		if self.regs[PC] == pc as u32 && !inst.ce {
+			return Ok(false)
+		}
+
+		Ok(true)
+	}
+
+	// runs until halted or `steps` instructions have run
+	pub fn run(&mut self, steps: usize) -> Result<(), Fault> {
+		while self.step()? {
+			if self.steps >= steps {
+				return Err(Fault::Timeout)
+			}
+		}
+
+		Ok(())
+	}
+}
@@ -1,12 +1,19 @@
+use std::fmt;
+
 use loc::Loc;
 use token::Token;
+use diag::Exit;
 
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum ErrorId {
 	// Errors generated by lexer
 	InvalidReg,
 	InvalidChar,
+	NonAscii(char),
+	UnterminatedString,
+	UnterminatedComment,
+	NumberTooLarge,
 
 	// Errors generated by parser
 	ExpectedProgram,
@@ -14,9 +21,107 @@ pub enum ErrorId {
 	ExpectedAtom,
 	ExpectedParen,
 	ExpectedSquare,
+	ExpectedIden,
+	ExpectedReg,
+	AliasShadowsLabel(String),
+	InvalidDirective,
+	ExpectedStr,
+	IncludeNotFound,
+	IncludeCycle,
 
 	InvalidNode,
-	InvalidInstruction
+	InvalidInstruction,
+	NoScratch,
+	ScratchRead(u8),
+
+	// Errors generated by code generation
+	UndefinedLabel(String),
+	DuplicateLabel(String),
+	UndefinedGlobal(String),
+
+	// Errors generated by linker script parser
+	InvalidScript
+}
+
+impl ErrorId {
+	// variant name, stable for tools
+	pub fn name(&self) -> &'static str {
+		match *self {
+			ErrorId::InvalidReg => "InvalidReg",
+			ErrorId::InvalidChar => "InvalidChar",
+			ErrorId::NonAscii(..) => "NonAscii",
+			ErrorId::UnterminatedString => "UnterminatedString",
+			ErrorId::UnterminatedComment => "UnterminatedComment",
+			ErrorId::NumberTooLarge => "NumberTooLarge",
+			ErrorId::ExpectedProgram => "ExpectedProgram",
+			ErrorId::ExpectedLine => "ExpectedLine",
+			ErrorId::ExpectedAtom => "ExpectedAtom",
+			ErrorId::ExpectedParen => "ExpectedParen",
+			ErrorId::ExpectedSquare => "ExpectedSquare",
+			ErrorId::ExpectedIden => "ExpectedIden",
+			ErrorId::ExpectedReg => "ExpectedReg",
+			ErrorId::AliasShadowsLabel(..) => "AliasShadowsLabel",
+			ErrorId::InvalidDirective => "InvalidDirective",
+			ErrorId::ExpectedStr => "ExpectedStr",
+			ErrorId::IncludeNotFound => "IncludeNotFound",
+			ErrorId::IncludeCycle => "IncludeCycle",
+			ErrorId::InvalidNode => "InvalidNode",
+			ErrorId::InvalidInstruction => "InvalidInstruction",
+			ErrorId::NoScratch => "NoScratch",
+			ErrorId::ScratchRead(_) => "ScratchRead",
+			ErrorId::InvalidScript => "InvalidScript",
+			ErrorId::UndefinedLabel(..) => "UndefinedLabel",
+			ErrorId::DuplicateLabel(..) => "DuplicateLabel",
+			ErrorId::UndefinedGlobal(..) => "UndefinedGlobal"
+		}
+	}
+
+	pub fn message(&self) -> String {
+		match *self {
+			ErrorId::InvalidReg => String::from("invalid register"),
+			ErrorId::InvalidChar => String::from("invalid character"),
+			ErrorId::NonAscii(chr) =>
+				format!("non-ASCII character `{}` (U+{:04X}) is only allowed in strings and comments", chr, chr as u32),
+			ErrorId::UnterminatedString => String::from("unterminated string"),
+			ErrorId::UnterminatedComment => String::from("unterminated block comment"),
+			ErrorId::NumberTooLarge => String::from("number does not fit in 32 bits"),
+			ErrorId::ExpectedProgram => String::from("expected a statement or label"),
+			ErrorId::ExpectedLine => String::from("expected the end of the line"),
+			ErrorId::ExpectedAtom => String::from("expected a number, label, register or `(`"),
+			ErrorId::ExpectedParen => String::from("expected `)`"),
+			ErrorId::ExpectedSquare => String::from("expected `]`"),
+			ErrorId::ExpectedIden => String::from("expected a label"),
+			ErrorId::ExpectedReg => String::from("expected a register"),
+			ErrorId::AliasShadowsLabel(ref name) => format!("register alias `{}` shadows a label of the same name", name),
+			ErrorId::InvalidDirective => String::from("unknown directive"),
+			ErrorId::ExpectedStr => String::from("expected a string"),
+			ErrorId::IncludeNotFound => String::from("included file not found"),
+			ErrorId::IncludeCycle => String::from("file includes itself"),
+			ErrorId::InvalidNode => String::from("invalid expression"),
+			ErrorId::InvalidInstruction => String::from("invalid instruction"),
+			ErrorId::NoScratch => String::from("not enough scratch registers for the expression, `.scratch` sets one"),
+			ErrorId::ScratchRead(reg) => format!("instruction reads the scratch register r{} used by its expression", reg),
+			ErrorId::InvalidScript => String::from("invalid linker script line"),
+			ErrorId::UndefinedLabel(ref label) => format!("undefined label `{}`", label),
+			ErrorId::DuplicateLabel(ref label) => format!("label `{}` is already defined", label),
+			ErrorId::UndefinedGlobal(ref label) => format!("global label `{}` is not defined", label)
+		}
+	}
+
+	// process exit code, every id is listed so new ones have to pick one
+	pub fn exit(&self) -> Exit {
+		match *self {
+			ErrorId::InvalidReg | ErrorId::InvalidChar | ErrorId::NonAscii(_) | ErrorId::UnterminatedString |
+			ErrorId::UnterminatedComment | ErrorId::NumberTooLarge | ErrorId::ExpectedProgram |
+			ErrorId::ExpectedLine | ErrorId::ExpectedAtom | ErrorId::ExpectedParen | ErrorId::ExpectedSquare |
+			ErrorId::ExpectedIden | ErrorId::ExpectedReg | ErrorId::AliasShadowsLabel(_) |
+			ErrorId::InvalidDirective | ErrorId::ExpectedStr | ErrorId::IncludeCycle | ErrorId::InvalidNode |
+			ErrorId::InvalidScript => Exit::Syntax,
+			ErrorId::IncludeNotFound => Exit::Io,
+			ErrorId::InvalidInstruction | ErrorId::NoScratch | ErrorId::ScratchRead(_) |
+			ErrorId::UndefinedLabel(_) | ErrorId::DuplicateLabel(_) | ErrorId::UndefinedGlobal(_) => Exit::Codegen
+		}
+	}
 }
 
 #[derive(Debug, Clone)]
@@ -25,6 +130,15 @@ pub struct Error {
 	pub loc: Loc
 }
 
+// `line:col: message`, the file name is only known to `Files`
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}:{}: {}", self.loc.line, self.loc.col, self.id.message())
+	}
+}
+
+impl std::error::Error for Error {}
+
 
 pub fn gen_error<T>(error_id: ErrorId, token: Token) -> Result<T, Error> {
 	Err(Error {
@@ -37,4 +151,35 @@ pub fn gen_error<T>(error_id: ErrorId, token: Token) -> Result<T, Error> {
 pub fn print_error(error: &Error) {
 
 }
-*/
\ No newline at end of file
+*/
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn display() {
+		let err = Error {
+			id: ErrorId::UndefinedLabel(String::from("loop")),
+			loc: Loc {
+				pos: 10,
+				col: 5,
+				line: 2,
+				file: 0
+			}
+		};
+
+		assert_eq!(err.to_string(), "2:5: undefined label `loop`");
+
+		let boxed: Box<dyn std::error::Error> = Box::new(err);
+		assert_eq!(boxed.to_string(), "2:5: undefined label `loop`");
+	}
+
+	#[test]
+	fn exit_codes() {
+		assert_eq!(ErrorId::ExpectedParen.exit(), Exit::Syntax);
+		assert_eq!(ErrorId::IncludeNotFound.exit(), Exit::Io);
+		assert_eq!(ErrorId::DuplicateLabel(String::from("a")).exit(), Exit::Codegen);
+	}
+}
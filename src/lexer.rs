@@ -1,55 +1,81 @@
+use std::collections::HashMap;
+
 use loc::Loc;
 use token::{TokenId, Token};
 use error::{ErrorId, Error};
 
 
+// `loc.pos` is the byte offset of `chr_maybe` in `source`
 #[derive(Debug)]
 pub struct Lexer {
 	source: String,
 	loc: Loc,
-	chr_maybe: Option<char>
+	// location of the first character of the current token
+	start: Loc,
+	chr_maybe: Option<char>,
+	// register names, `pc`, `ra` and `sp` and those set by `.reg`
+	aliases: HashMap<String, u8>
 }
 
 impl Lexer {
-	pub fn new(source: String) -> Lexer {
+	pub fn new(source: String, file: usize) -> Lexer {
+		let loc = Loc {
+			pos: 0,
+			col: 1,
+			line: 1,
+			file
+		};
+
+		let mut aliases = HashMap::new();
+		aliases.insert(String::from("pc"), 15);
+		aliases.insert(String::from("ra"), 14);
+		aliases.insert(String::from("sp"), 13);
+
 		Lexer {
-			chr_maybe: source.chars().nth(0),
-			source: source,
-			loc: Loc {
-				pos: 0,
-				col: 1,
-				line: 1
-			}
+			chr_maybe: source.chars().next(),
+			source,
+			start: loc.clone(),
+			loc,
+			aliases
 		}
 	}
 
-	fn advance(&mut self) {
-		if self.chr_maybe.is_some() {
-			let chr = self.chr_maybe.unwrap();
+	// tokens lexed after this see the alias
+	pub fn set_alias(&mut self, name: String, reg: u8) {
+		self.aliases.insert(name, reg);
+	}
 
+	pub fn remove_alias(&mut self, name: &str) {
+		self.aliases.remove(name);
+	}
+
+	fn advance(&mut self) {
+		if let Some(chr) = self.chr_maybe {
 			self.loc.col += 1;
+			self.loc.pos += chr.len_utf8();
 
 			if chr == '\n' {
 				self.loc.line += 1;
 				self.loc.col = 1;
 			}
-		}
 
-		self.loc.pos += 1;
-
-		self.chr_maybe = self.source.chars().nth(self.loc.pos);
+			self.chr_maybe = self.source[self.loc.pos..].chars().next();
+		}
 	}
 
-	fn get_num(&mut self, chr: char) -> isize {
-		let mut num: isize = chr.to_digit(10).unwrap() as isize;
+	// `None` when the number does not fit in 32 bits, the rest of its digits are still consumed
+	fn get_num(&mut self, chr: char) -> Option<isize> {
+		let mut num = Some(chr.to_digit(10).unwrap() as isize);
 
 		loop {
 			match self.chr_maybe {
 				None => break,
 				Some(chr) => match chr {
-					'0' ... '9' => {
-						num = num * 10;
-						num += chr.to_digit(10).unwrap() as isize;
+					'0' ..= '9' => {
+						num = num
+							.and_then(|num| num.checked_mul(10))
+							.and_then(|num| num.checked_add(chr.to_digit(10).unwrap() as isize))
+							.filter(|&num| num <= u32::MAX as isize);
 
 						self.advance()
 					},
@@ -61,6 +87,7 @@ impl Lexer {
 		num
 	}
 
+	// `.` may only follow the first character, a leading `.` is a directive
 	fn get_iden(&mut self, chr: char) -> String {
 		let mut iden = chr.to_string();
 
@@ -68,7 +95,7 @@ impl Lexer {
 			match self.chr_maybe {
 				None => break,
 				Some(chr) => match chr {
-					'a' ... 'z' | 'A' ... 'Z' | '0' ... '9' | '_' => {
+					'a' ..= 'z' | 'A' ..= 'Z' | '0' ..= '9' | '_' | '.' => {
 						iden.push(chr);
 
 						self.advance();
@@ -84,29 +111,80 @@ impl Lexer {
 	fn gen_token(&self, token_id: TokenId) -> Result<Token, Error> {
 		Ok(Token {
 			id: token_id,
-			loc: self.loc.clone()
+			loc: self.start.clone()
 		})
 	}
 
-	fn gen_error(&self, error_id: ErrorId, offset: usize) -> Result<Token, Error> {
+	// errors point at the start of the token
+	fn gen_error(&self, error_id: ErrorId) -> Result<Token, Error> {
 		Err(Error {
 			id: error_id,
-			loc: Loc {
-				pos: self.loc.pos,
-				col: self.loc.col - offset,
-				line: self.loc.line
-			}
+			loc: self.start.clone()
 		})
 	}
 
+	// `r0` to `r15`, `None` if `iden` is not a register
+	fn get_reg(&self, iden: &str) -> Option<Result<Token, Error>> {
+		if iden.len() < 2 || !iden.starts_with('r') || !iden[1..].bytes().all(|byte| byte.is_ascii_digit()) {
+			return None
+		}
+
+		Some(match iden[1..].parse::<u8>() {
+			Ok(reg) if reg <= 15 => self.gen_token(TokenId::Reg(reg)),
+			_ => self.gen_error(ErrorId::InvalidReg)
+		})
+	}
+
+	// skips to the `*/` matching an opening `/*`, comments may nest
+	// lines inside the comment are not tokens
+	fn skip_block_comment(&mut self) -> Result<(), Error> {
+		let mut depth = 1;
+
+		while depth > 0 {
+			match self.chr_maybe {
+				// points at the outermost `/*`
+				None => return Err(Error {
+					id: ErrorId::UnterminatedComment,
+					loc: self.start.clone()
+				}),
+				Some('/') => {
+					self.advance();
+
+					if self.chr_maybe == Some('*') {
+						self.advance();
+						depth += 1;
+					}
+				},
+				Some('*') => {
+					self.advance();
+
+					if self.chr_maybe == Some('/') {
+						self.advance();
+						depth -= 1;
+					}
+				},
+				Some(_) => self.advance()
+			}
+		}
+
+		Ok(())
+	}
+
 	pub fn token(&mut self) -> Result<Token, Error> {
+		// spaces only separate tokens
+		while self.chr_maybe == Some(' ') {
+			self.advance();
+		}
+
+		self.start = self.loc.clone();
+
 		match self.chr_maybe {
 			None => self.gen_token(TokenId::Eof),
 			Some(chr) => match chr {
-				' ' => {
+				';' => {
 					self.advance();
 
-					self.token()
+					self.gen_token(TokenId::Semi)
 				},
 				'#' => {
 					self.advance();
@@ -115,76 +193,76 @@ impl Lexer {
 						match self.chr_maybe {
 							None => return self.gen_token(TokenId::Eof),
 							Some(chr) => match chr {
-								'\n' => {
-									self.advance();
-
-									return self.token()
-								},
+								// the line ends the comment but is still a token
+								'\n' => return self.token(),
 								_ => self.advance()
 							}
 						}
 					}
 				},
-				'a' ... 'z' => {
-					if chr == 'r' {
-						self.advance();
+				'a' ..= 'z' | 'A' ..= 'Z' | '_' => {
+					self.advance();
 
-						match self.chr_maybe {
-							None => self.gen_token(TokenId::Eof),
-							Some(chr) => match chr {
-								'0' ... '9' => {
-									self.advance();
-
-									let num: isize = self.get_num(chr);
-
-									if num > 15 {
-										self.gen_error(ErrorId::InvalidReg, 1)
-									} else {
-										self.gen_token(TokenId::Reg(num as u8))
-									}
-								},
-								_ => {
-									self.advance();
-
-									let iden = self.get_iden(chr);
-
-									match iden.as_ref() {
-										"m8" => self.gen_token(TokenId::Mem8),
-										"m16" => self.gen_token(TokenId::Mem16),
-										"m32" => self.gen_token(TokenId::Mem32),
-										_ => self.gen_token(TokenId::Iden(iden))
-									}
-								}		
-							}
-						}
-					} else {
-						self.advance();
+					let iden = self.get_iden(chr);
 
-						let iden = self.get_iden(chr);
+					if let Some(reg) = self.get_reg(&iden) {
+						return reg
+					}
 
-						match iden.as_ref() {
-							"m8" => self.gen_token(TokenId::Mem8),
-							"m16" => self.gen_token(TokenId::Mem16),
-							"m32" => self.gen_token(TokenId::Mem32),
-							_ => self.gen_token(TokenId::Iden(iden))
-						}
+					if let Some(&reg) = self.aliases.get(&iden) {
+						return self.gen_token(TokenId::Alias(iden, reg))
+					}
+
+					match iden.as_ref() {
+						"_" => self.gen_token(TokenId::Empty),
+						"m8" => self.gen_token(TokenId::Mem8),
+						"m16" => self.gen_token(TokenId::Mem16),
+						"m32" => self.gen_token(TokenId::Mem32),
+						_ => self.gen_token(TokenId::Iden(iden))
 					}
 				},
-				'0' ... '9' => {
+				'0' ..= '9' => {
 					self.advance();
 
-					let num = self.get_num(chr);
-					self.gen_token(TokenId::Num(num))
+					match self.get_num(chr) {
+						Some(num) => self.gen_token(TokenId::Num(num)),
+						None => self.gen_error(ErrorId::NumberTooLarge)
+					}
 				},
-				'_' => {
+				'.' => {
 					self.advance();
 
-					self.gen_token(TokenId::Empty)
-				}
+					match self.chr_maybe {
+						Some(chr @ 'a' ..= 'z') => {
+							self.advance();
+
+							let iden = self.get_iden(chr);
+							self.gen_token(TokenId::Directive(iden))
+						},
+						_ => self.gen_error(ErrorId::InvalidChar)
+					}
+				},
 				'!' => {
 					self.advance();
 
-					self.gen_token(TokenId::Not)
+					match self.chr_maybe {
+						Some('|') => {
+							self.advance();
+
+							self.gen_token(TokenId::Nor)
+						},
+						Some('&') => {
+							self.advance();
+
+							self.gen_token(TokenId::Nand)
+						},
+						Some('^') => {
+							self.advance();
+
+							self.gen_token(TokenId::Xnor)
+						},
+						_ => self.gen_token(TokenId::Not)
+					}
 				},
 				'|' => {
 					self.advance();
@@ -228,7 +306,15 @@ impl Lexer {
 				'/' => {
 					self.advance();
 
-					self.gen_token(TokenId::Div)
+					match self.chr_maybe {
+						Some('*') => {
+							self.advance();
+							self.skip_block_comment()?;
+
+							self.token()
+						},
+						_ => self.gen_token(TokenId::Div)
+					}
 				},
 				',' => {
 					self.advance();
@@ -268,6 +354,27 @@ impl Lexer {
 						}
 					}
 				},
+				'"' => {
+					self.advance();
+
+					let mut string = String::new();
+
+					loop {
+						match self.chr_maybe {
+							None | Some('\n') => return self.gen_error(ErrorId::UnterminatedString),
+							Some('"') => {
+								self.advance();
+
+								return self.gen_token(TokenId::Str(string))
+							},
+							Some(chr) => {
+								string.push(chr);
+
+								self.advance();
+							}
+						}
+					}
+				},
 				'?' => {
 					self.advance();
 
@@ -293,6 +400,27 @@ impl Lexer {
 
 					self.gen_token(TokenId::SquareR)
 				},
+				// `\` at the end of a line continues the statement on the next line
+				'\\' => {
+					self.advance();
+
+					while self.chr_maybe == Some(' ') {
+						self.advance();
+					}
+
+					if self.chr_maybe != Some('\n') {
+						return self.gen_error(ErrorId::InvalidChar)
+					}
+
+					self.advance();
+
+					// indentation of the continued line is not a `Tab`
+					while self.chr_maybe == Some(' ') || self.chr_maybe == Some('\t') {
+						self.advance();
+					}
+
+					self.token()
+				},
 				'\t' => {
 					self.advance();
 
@@ -303,8 +431,141 @@ impl Lexer {
 
 					self.gen_token(TokenId::Line)
 				},
-				_ => self.gen_error(ErrorId::InvalidChar, 0)
+				_ if !chr.is_ascii() => self.gen_error(ErrorId::NonAscii(chr)),
+				_ => self.gen_error(ErrorId::InvalidChar)
 			}
 		}
 	}
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+	use super::Lexer;
+	use assembler::assemble;
+	use token::{TokenId, Token};
+	use error::{ErrorId, Error};
+
+	// every token before the end of the source
+	fn tokens(source: &str) -> Result<Vec<Token>, Error> {
+		let mut lexer = Lexer::new(String::from(source), 0);
+		let mut tokens = vec![];
+
+		loop {
+			let token = lexer.token()?;
+
+			if token.id == TokenId::Eof {
+				return Ok(tokens)
+			}
+
+			tokens.push(token);
+		}
+	}
+
+	fn ids(source: &str) -> Vec<TokenId> {
+		tokens(source).unwrap().into_iter().map(|token| token.id).collect()
+	}
+
+	fn iden(name: &str) -> TokenId {
+		TokenId::Iden(String::from(name))
+	}
+
+	#[test]
+	fn largest_number() {
+		let mut lexer = Lexer::new(String::from("4294967295"), 0);
+
+		assert_eq!(lexer.token().unwrap().id, TokenId::Num(4294967295));
+	}
+
+	#[test]
+	fn number_too_large() {
+		for source in ["4294967296", "99999999999999999999999999"] {
+			let mut lexer = Lexer::new(String::from(source), 0);
+			let err = lexer.token().unwrap_err();
+
+			assert_eq!(err.id, ErrorId::NumberTooLarge);
+			assert_eq!(err.loc.col, 1);
+		}
+	}
+
+	#[test]
+	fn identifiers() {
+		assert_eq!(
+			ids("loop_2 _tmp Main x1 a.b r1x"),
+			[iden("loop_2"), iden("_tmp"), iden("Main"), iden("x1"), iden("a.b"), iden("r1x")]
+		);
+		assert_eq!(ids("_ r15"), [TokenId::Empty, TokenId::Reg(15)]);
+		assert_eq!(ids("2x"), [TokenId::Num(2), iden("x")]);
+	}
+
+	#[test]
+	fn invalid_register() {
+		assert_eq!(tokens("r16").unwrap_err().id, ErrorId::InvalidReg);
+	}
+
+	#[test]
+	fn non_ascii() {
+		let err = tokens("\tr0 -> 1\n\tr1 -> caf\u{e9}\n").unwrap_err();
+
+		assert_eq!(err.id, ErrorId::NonAscii('\u{e9}'));
+		assert_eq!((err.loc.line, err.loc.col), (2, 11));
+	}
+
+	#[test]
+	fn non_ascii_in_strings_and_comments() {
+		assert_eq!(ids("\"\u{e9}\" # \u{e9}"), [TokenId::Str(String::from("\u{e9}"))]);
+	}
+
+	#[test]
+	fn nested_block_comments() {
+		assert_eq!(ids("1 /* a /* b */ c */ 2"), [TokenId::Num(1), TokenId::Num(2)]);
+		assert_eq!(ids("1 /* /* */ */ / 2"), [TokenId::Num(1), TokenId::Div, TokenId::Num(2)]);
+	}
+
+	#[test]
+	fn unterminated_block_comment() {
+		// points at the outermost `/*`
+		let err = tokens("\tr0 -> 1\n /* a /* b */\n c\n").unwrap_err();
+
+		assert_eq!(err.id, ErrorId::UnterminatedComment);
+		assert_eq!((err.loc.line, err.loc.col), (2, 2));
+	}
+
+	#[test]
+	fn lines_after_block_comment() {
+		let tokens = tokens("/* a\nb\n*/ 1\n\t2").unwrap();
+		let locs: Vec<(usize, usize)> = tokens.iter().map(|token| (token.loc.line, token.loc.col)).collect();
+
+		assert_eq!(tokens[0].id, TokenId::Num(1));
+		assert_eq!(locs, [(3, 4), (3, 5), (4, 1), (4, 2)]);
+	}
+
+	#[test]
+	fn diagnostic_after_block_comment() {
+		let errors = assemble("/* a\n\tr0 -> 1\n*/\n\tr0 -> 1 /* b\n */\n\t5 -> r1\n").unwrap_err();
+
+		assert_eq!(errors[0].id, ErrorId::InvalidInstruction);
+		assert_eq!((errors[0].loc.line, errors[0].loc.col), (6, 2));
+	}
+
+	#[test]
+	fn line_continuation() {
+		let tokens = tokens("\tr0 -> r1 + \\\n\t\t4\n").unwrap();
+		let ids: Vec<TokenId> = tokens.iter().map(|token| token.id.clone()).collect();
+
+		assert_eq!(ids, [
+			TokenId::Tab,
+			TokenId::Reg(0),
+			TokenId::To,
+			TokenId::Reg(1),
+			TokenId::Add,
+			TokenId::Num(4),
+			TokenId::Line
+		]);
+		assert_eq!((tokens[5].loc.line, tokens[5].loc.col), (2, 3));
+	}
+
+	#[test]
+	fn continuation_must_end_the_line() {
+		assert_eq!(tokens("\tr0 -> 1 \\ 2\n").unwrap_err().id, ErrorId::InvalidChar);
+	}
+}
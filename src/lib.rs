@@ -0,0 +1,48 @@
+#![feature(box_patterns)]
+
+mod loc;
+mod token;
+mod error;
+mod lexer;
+mod node;
+mod parser;
+mod cpu;
+mod source;
+mod assembler;
+mod builder;
+mod listing;
+mod map;
+mod output;
+mod object;
+mod script;
+mod linker;
+mod archive;
+mod disasm;
+mod emu;
+mod diag;
+mod depfile;
+mod cli;
+mod driver;
+
+// assembler
+pub use loc::Loc;
+pub use token::{Token, TokenId};
+pub use error::{ErrorId, Error};
+pub use lexer::Lexer;
+pub use parser::parse;
+pub use node::Program;
+pub use cpu::{Opcode, Inst};
+pub use assembler::{Assembly, Assembler, assemble};
+pub use builder::{Builder, Reg, Operand, r, imm, label};
+
+// objects and the linker
+pub use object::Object;
+pub use archive::Archive;
+pub use script::Script;
+pub use linker::{LinkError, Image, link, pull};
+
+// output formats
+pub use output::{Format, Endian, Options, OutputError, output};
+
+// the `jcpu-asm` command line
+pub use driver::run_cli;
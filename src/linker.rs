@@ -0,0 +1,401 @@
+use std::collections::{HashMap, HashSet};
+
+use object::Object;
+use archive::Archive;
+use script::Script;
+use map::{Symbol, set_sizes};
+
+
+#[derive(Debug, Clone)]
+pub enum LinkError {
+	// label, object referencing it
+	Undefined(String, String),
+	// label, object referencing it, object defining it without `.global`
+	NotExported(String, String, String),
+	// label, objects defining it
+	MultiplyDefined(String, String, String),
+	// section without a region in the script
+	Unplaced(String),
+	// region, words past its end
+	Overflow(String, usize),
+	// sections placed over each other
+	Overlap(String, String),
+	// entry label
+	NoEntry(String)
+}
+
+impl LinkError {
+	pub fn id(&self) -> &'static str {
+		match *self {
+			LinkError::Undefined(..) => "Undefined",
+			LinkError::NotExported(..) => "NotExported",
+			LinkError::MultiplyDefined(..) => "MultiplyDefined",
+			LinkError::Unplaced(..) => "Unplaced",
+			LinkError::Overflow(..) => "Overflow",
+			LinkError::Overlap(..) => "Overlap",
+			LinkError::NoEntry(..) => "NoEntry"
+		}
+	}
+
+	pub fn message(&self) -> String {
+		match *self {
+			LinkError::Undefined(ref label, ref object) =>
+				format!("undefined label `{}` referenced in {}", label, object),
+			LinkError::NotExported(ref label, ref object, ref defining) =>
+				format!("label `{}` referenced in {} is not exported by {}", label, object, defining),
+			LinkError::MultiplyDefined(ref label, ref first, ref second) =>
+				format!("label `{}` defined in both {} and {}", label, first, second),
+			LinkError::Unplaced(ref section) =>
+				format!("section `{}` is not placed in any region", section),
+			LinkError::Overflow(ref region, words) =>
+				format!("region `{}` overflowed by {} words", region, words),
+			LinkError::Overlap(ref first, ref second) =>
+				format!("sections `{}` and `{}` overlap", first, second),
+			LinkError::NoEntry(ref label) =>
+				format!("entry label `{}` is not defined", label)
+		}
+	}
+}
+
+// linked program
+#[derive(Debug, Clone)]
+pub struct Image {
+	// address and words of each placed section, by address
+	pub segments: Vec<(usize, Vec<u32>)>,
+	pub symbols: Vec<Symbol>,
+	pub entry: Option<usize>
+}
+
+// output section
+struct Placed {
+	name: String,
+	start: usize,
+	end: usize
+}
+
+// adds the archive members defining labels the objects leave undefined
+// members are named `archive(member)`
+pub fn pull(mut objects: Vec<(String, Object)>, archives: &[(String, Archive)]) -> Vec<(String, Object)> {
+	let mut pulled: Vec<(usize, usize)> = vec![];
+
+	loop {
+		let found = {
+			let defined: HashSet<&String> = objects
+				.iter()
+				.flat_map(|(_, object)| object.globals().map(|symbol| &symbol.name))
+				.collect();
+
+			objects
+				.iter()
+				.flat_map(|(_, object)| object.needed())
+				.filter(|label| !defined.contains(label))
+				.flat_map(|label| archives
+					.iter()
+					.enumerate()
+					.filter_map(move |(i, (_, archive))| archive.find(label).map(|member| (i, member))))
+				.find(|member| !pulled.contains(member))
+		};
+
+		match found {
+			None => return objects,
+			Some((i, member)) => {
+				let (archive_name, archive) = &archives[i];
+				let (member_name, object) = &archive.members[member];
+
+				objects.push((format!("{}({})", archive_name, member_name), object.clone()));
+				pulled.push((i, member));
+			}
+		}
+	}
+}
+
+// `objects` are paired with the name used in errors
+pub fn link(objects: &[(String, Object)], script: &Script) -> Result<Image, Vec<LinkError>> {
+	let mut errors = vec![];
+
+	// output sections, placed ones first
+	let mut names: Vec<String> = script.places
+		.iter()
+		.map(|place| place.0.clone())
+		.collect();
+
+	for (_, object) in objects {
+		for section in &object.sections {
+			if !names.contains(&section.name) {
+				names.push(section.name.clone());
+			}
+		}
+	}
+
+	// address of each section of each object
+	let mut bases: Vec<Vec<usize>> = objects
+		.iter()
+		.map(|(_, object)| vec![0; object.sections.len()])
+		.collect();
+
+	let mut nexts: Vec<usize> = script.regions.iter().map(|region| region.origin).collect();
+	let mut placed: Vec<Placed> = vec![];
+
+	for name in names {
+		let region = match script.region(&name) {
+			None => {
+				errors.push(LinkError::Unplaced(name));
+				continue
+			},
+			Some(region) => region
+		};
+
+		let start = nexts[region];
+		let mut next = start;
+
+		for (i, (_, object)) in objects.iter().enumerate() {
+			for (j, section) in object.sections.iter().enumerate() {
+				if section.name == name {
+					bases[i][j] = next;
+					next += section.binary.len();
+				}
+			}
+		}
+
+		nexts[region] = next;
+		placed.push(Placed { name, start, end: next });
+	}
+
+	for (region, next) in script.regions.iter().zip(&nexts) {
+		let end = region.origin.saturating_add(region.len);
+
+		if *next > end {
+			errors.push(LinkError::Overflow(region.name.clone(), next - end));
+		}
+	}
+
+	let mut by_start: Vec<&Placed> = placed.iter().filter(|p| p.end > p.start).collect();
+	by_start.sort_by_key(|p| p.start);
+
+	for pair in by_start.windows(2) {
+		if pair[1].start < pair[0].end {
+			errors.push(LinkError::Overlap(pair[0].name.clone(), pair[1].name.clone()));
+		}
+	}
+
+	// exported label address and defining object
+	let mut labels: HashMap<&String, (usize, usize)> = HashMap::new();
+
+	for (i, (object_name, object)) in objects.iter().enumerate() {
+		for symbol in object.globals() {
+			let addr = bases[i][symbol.section] + symbol.addr;
+
+			match labels.get(&symbol.name) {
+				Some(&(_, first)) => errors.push(LinkError::MultiplyDefined(
+					symbol.name.clone(),
+					objects[first].0.clone(),
+					object_name.clone()
+				)),
+				None => {
+					labels.insert(&symbol.name, (addr, i));
+				}
+			}
+		}
+	}
+
+	for (object_name, object) in objects {
+		for label in object.needed() {
+			if labels.contains_key(label) {
+				continue
+			}
+
+			match objects.iter().find(|(_, other)| other.find(label).is_some()) {
+				None => errors.push(LinkError::Undefined(label.clone(), object_name.clone())),
+				Some((defining, _)) => errors.push(LinkError::NotExported(
+					label.clone(),
+					object_name.clone(),
+					defining.clone()
+				))
+			}
+		}
+	}
+
+	let entry = match script.entry {
+		None => None,
+		Some(ref label) => match labels.get(label) {
+			None => {
+				errors.push(LinkError::NoEntry(label.clone()));
+				None
+			},
+			Some(&(addr, _)) => Some(addr)
+		}
+	};
+
+	if !errors.is_empty() {
+		return Err(errors)
+	}
+
+	// one segment per non-empty output section, gaps between them are never allocated
+	let mut segments: Vec<(usize, Vec<u32>)> = by_start
+		.iter()
+		.map(|p| (p.start, vec![0; p.end - p.start]))
+		.collect();
+
+	for (i, (_, object)) in objects.iter().enumerate() {
+		// segment of each section, `None` for empty output sections
+		let places: Vec<Option<usize>> = object.sections
+			.iter()
+			.map(|section| by_start.iter().position(|p| p.name == section.name))
+			.collect();
+
+		for (j, section) in object.sections.iter().enumerate() {
+			let segment = match places[j] {
+				None => continue,
+				Some(k) => &mut segments[k]
+			};
+
+			let base = bases[i][j] - segment.0;
+
+			segment.1[base..base + section.binary.len()].copy_from_slice(&section.binary);
+		}
+
+		for reloc in &object.relocs {
+			let segment = match places[reloc.section] {
+				None => continue,
+				Some(k) => &mut segments[k]
+			};
+
+			let addr = bases[i][reloc.section] + reloc.addr - segment.0;
+
+			// labels of the object itself come first
+			let value = match object.find(&reloc.label) {
+				Some(symbol) => Some(bases[i][symbol.section] + symbol.addr),
+				None => labels.get(&reloc.label).map(|&(value, _)| value)
+			};
+
+			if let Some(value) = value {
+				segment.1[addr] = segment.1[addr].wrapping_add(value as u32);
+			}
+		}
+	}
+
+	let mut symbols: Vec<Symbol> = vec![];
+
+	for (i, (_, object)) in objects.iter().enumerate() {
+		for symbol in &object.symbols {
+			symbols.push(Symbol {
+				name: symbol.name.clone(),
+				section: object.sections[symbol.section].name.clone(),
+				addr: bases[i][symbol.section] + symbol.addr,
+				size: 0
+			});
+		}
+	}
+
+	let ends = placed.iter().map(|p| (p.name.clone(), p.end)).collect();
+	set_sizes(&mut symbols, &ends);
+
+	Ok(Image {
+		segments,
+		symbols,
+		entry
+	})
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use assembler::{assemble, Assembler};
+
+	fn object(name: &str, source: &str) -> (String, Object) {
+		let mut assembler = Assembler::new();
+		assembler.object = true;
+
+		(String::from(name), Object::new(&assembler.assemble(name, String::from(source)).unwrap()))
+	}
+
+	fn errors(objects: &[(String, Object)], script: &str) -> Vec<String> {
+		let script = Script::parse(script).unwrap();
+
+		link(objects, &script)
+			.unwrap_err()
+			.iter()
+			.map(|err| err.message())
+			.collect()
+	}
+
+	#[test]
+	fn relocation() {
+		let objects = [
+			object("a.o", ".extern f\n\tcall f\n\thalt\n"),
+			object("b.o", ".global f\nf\n\tret\n")
+		];
+
+		let image = link(&objects, &Script::new()).unwrap();
+
+		let binary = assemble("\tcall f\n\thalt\nf\n\tret\n").unwrap().binary().to_vec();
+
+		assert_eq!(image.segments, [(0, binary)]);
+	}
+
+	#[test]
+	fn distant_regions_are_separate_segments() {
+		let objects = [object("a.o", "\tr0 -> 1\n.section data\n\t(5)\n")];
+		let script = Script::parse("region rom 0 0x100\nregion ram 0x80000000 0x100\nplace text rom\nplace data ram\n").unwrap();
+
+		let image = link(&objects, &script).unwrap();
+
+		assert_eq!(image.segments.len(), 2);
+		assert_eq!(image.segments[0].0, 0);
+		assert_eq!(image.segments[1], (0x80000000, vec![5]));
+	}
+
+	#[test]
+	fn multiply_defined() {
+		let objects = [
+			object("a.o", ".global f\nf\n\tret\n"),
+			object("b.o", ".global f\nf\n\tret\n")
+		];
+
+		assert_eq!(errors(&objects, "region mem 0 0x100\n"), ["label `f` defined in both a.o and b.o"]);
+	}
+
+	#[test]
+	fn not_exported() {
+		let objects = [
+			object("a.o", ".extern f\n\tcall f\n"),
+			object("b.o", "f\n\tret\n")
+		];
+
+		assert_eq!(errors(&objects, "region mem 0 0x100\n"), ["label `f` referenced in a.o is not exported by b.o"]);
+	}
+
+	#[test]
+	fn overlap() {
+		let objects = [
+			object("a.o", "\tr0 -> 1\n\tr1 -> 2\n\tr2 -> 3\n"),
+			object("b.o", ".section data\n\t(1)\n")
+		];
+
+		assert_eq!(
+			errors(&objects, "region rom 0 8\nregion ram 2 8\nplace text rom\nplace data ram\n"),
+			["sections `text` and `data` overlap"]
+		);
+	}
+
+	#[test]
+	fn overflow() {
+		let objects = [
+			object("a.o", "\tr0 -> 1\n"),
+			object("b.o", "\tr1 -> 2\n")
+		];
+
+		assert_eq!(errors(&objects, "region rom 0 3\n"), ["region `rom` overflowed by 1 words"]);
+	}
+
+	#[test]
+	fn no_entry() {
+		let objects = [
+			object("a.o", ".global main\nmain\n\thalt\n"),
+			object("b.o", "start\n\thalt\n")
+		];
+
+		assert_eq!(errors(&objects, "region mem 0 0x100\nentry start\n"), ["entry label `start` is not defined"]);
+	}
+}
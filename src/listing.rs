@@ -0,0 +1,102 @@
+use cpu::Inst;
+use node::Program;
+use map::symbols;
+use source::Files;
+use disasm::{decode_at, inst_source};
+
+
+fn fmt_inst(inst: &Inst) -> String {
+	format!(
+		"{:?} ce={} ci={} cond={} dest1={} dest0={} src1={} src0={} w1={} w0={} i1={} i0={}",
+		inst.opcode,
+		inst.ce as u8,
+		inst.ci as u8,
+		inst.cond,
+		inst.dest1,
+		inst.dest0,
+		inst.src1,
+		inst.src0,
+		inst.w1 as u8,
+		inst.w0 as u8,
+		inst.i1 as u8,
+		inst.i0 as u8
+	)
+}
+
+// assembled output next to the source of every file
+// must be called after `Program::gen`
+pub fn listing(files: &Files, program: &Program) -> String {
+	let mut out = String::new();
+
+	out.push_str(&format!("{:<6}{:<28}{:<6}SOURCE\n", "ADDR", "WORDS", "LINE"));
+
+	for (file, source) in files.sources.iter().enumerate() {
+		let mut records = program.records
+			.iter()
+			.filter(|record| record.file == file)
+			.peekable();
+
+		if files.sources.len() > 1 {
+			out.push_str(&format!("\nFILE {}\n", files.names[file]));
+		}
+
+		for (i, text) in source.lines().enumerate() {
+			let line = i + 1;
+			let mut first = true;
+
+			let mut line_records = vec![];
+
+			while let Some(record) = records.next_if(|record| record.line == line) {
+				line_records.push(record);
+			}
+
+			// a line lowered into several instructions shows each of them
+			let expanded = line_records.iter().filter(|record| record.inst).count() > 1;
+
+			if expanded {
+				out.push_str(&format!("{:<6}{:<28}{:<6}{}\n", "", "", line, text));
+			}
+
+			for record in line_records {
+				let addr = program.bases[record.section] + record.addr;
+				let words: Vec<String> = program.binary[addr..addr + record.len]
+					.iter()
+					.map(|word| format!("{:08x}", word))
+					.collect();
+
+				if expanded {
+					let source = decode_at(&program.binary, addr)
+						.and_then(|inst| inst_source(&inst))
+						.unwrap_or_default();
+
+					out.push_str(&format!("{:04x}  {:<28}{:<6}\t{}\n", addr, words.join(" "), "", source));
+				} else if first {
+					out.push_str(&format!("{:04x}  {:<28}{:<6}{}\n", addr, words.join(" "), line, text));
+				} else {
+					out.push_str(&format!("{:04x}  {}\n", addr, words.join(" ")));
+				}
+
+				if record.inst {
+					match Inst::decode(program.binary[addr]) {
+						None => out.push_str("      invalid opcode\n"),
+						Some(inst) => out.push_str(&format!("      {}\n", fmt_inst(&inst)))
+					}
+				}
+
+				first = false;
+			}
+
+			if first {
+				out.push_str(&format!("{:<6}{:<28}{:<6}{}\n", "", "", line, text));
+			}
+		}
+	}
+
+	out.push_str("\nSYMBOLS\n");
+
+	for symbol in symbols(program) {
+		out.push_str(&format!("{:04x}  {}\n", symbol.addr, symbol.name));
+	}
+
+	out
+}
@@ -1,6 +1,10 @@
-#[derive(Debug, Clone)]
+// all 0 when unknown
+#[derive(Debug, Clone, Default)]
 pub struct Loc {
+	// byte offset into the source
 	pub pos: usize,
 	pub col: usize,
-	pub line: usize
+	pub line: usize,
+	// index into `Files`
+	pub file: usize
 }
\ No newline at end of file
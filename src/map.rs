@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+
+use node::Program;
+
+
+// section of constants defined outside the source, e.g. `-D`
+pub const ABS: &str = "*ABS*";
+
+#[derive(Debug, Clone)]
+pub struct Symbol {
+	pub name: String,
+	pub section: String,
+	pub addr: usize,
+	// words up to the next symbol or the end of the section
+	pub size: usize
+}
+
+// sorts by address and sizes every symbol up to the next one in its section
+// `ends` holds the end address of each section
+pub fn set_sizes(symbols: &mut [Symbol], ends: &HashMap<String, usize>) {
+	symbols.sort_by(|a, b| (a.addr, &a.name).cmp(&(b.addr, &b.name)));
+
+	for i in 0..symbols.len() {
+		let addr = symbols[i].addr;
+		let end = symbols[i + 1..]
+			.iter()
+			.filter(|next| next.section == symbols[i].section)
+			.map(|next| next.addr)
+			.find(|&next| next > addr)
+			.unwrap_or(ends.get(&symbols[i].section).cloned().unwrap_or(addr));
+
+		symbols[i].size = end.max(addr) - addr;
+	}
+}
+
+// labels and defined constants sorted by address
+// must be called after `Program::gen`
+pub fn symbols(program: &Program) -> Vec<Symbol> {
+	let mut symbols: Vec<Symbol> = program.labels
+		.iter()
+		.map(|(name, label)| Symbol {
+			name: name.clone(),
+			section: program.sections[label.section].name.clone(),
+			addr: program.label_addr(label),
+			size: 0
+		})
+		.collect();
+
+	let ends = program.sections
+		.iter()
+		.enumerate()
+		.map(|(i, section)| (section.name.clone(), program.bases[i] + section.binary.len()))
+		.collect();
+
+	set_sizes(&mut symbols, &ends);
+
+	// constants take no space
+	symbols.extend(program.defines.iter().map(|(name, value)| Symbol {
+		name: name.clone(),
+		section: String::from(ABS),
+		addr: *value as usize,
+		size: 0
+	}));
+
+	symbols.sort_by(|a, b| (a.addr, &a.name).cmp(&(b.addr, &b.name)));
+	symbols
+}
+
+pub fn json_str(string: &str) -> String {
+	let mut out = String::from("\"");
+
+	for chr in string.chars() {
+		match chr {
+			'"' => out.push_str("\\\""),
+			'\\' => out.push_str("\\\\"),
+			'\n' => out.push_str("\\n"),
+			'\t' => out.push_str("\\t"),
+			'\u{0}' ..= '\u{1f}' => out.push_str(&format!("\\u{:04x}", chr as u32)),
+			_ => out.push(chr)
+		}
+	}
+
+	out.push('"');
+	out
+}
+
+pub fn map_text(symbols: &[Symbol], entry: Option<usize>) -> String {
+	let mut out = String::new();
+
+	if let Some(entry) = entry {
+		out.push_str(&format!("ENTRY {:08x}\n\n", entry));
+	}
+
+	out.push_str(&format!("{:<10}{:<10}{:<10}NAME\n", "ADDR", "SIZE", "SECTION"));
+
+	for symbol in symbols {
+		out.push_str(&format!("{:08x}  {:<8}  {:<8}  {}\n", symbol.addr, symbol.size, symbol.section, symbol.name));
+	}
+
+	out
+}
+
+pub fn map_json(symbols: &[Symbol], entry: Option<usize>) -> String {
+	let symbols: Vec<String> = symbols
+		.iter()
+		.map(|symbol| format!(
+			"\t\t{{\"name\": {}, \"section\": {}, \"addr\": {}, \"size\": {}}}",
+			json_str(&symbol.name),
+			json_str(&symbol.section),
+			symbol.addr,
+			symbol.size
+		))
+		.collect();
+
+	let entry = match entry {
+		None => String::from("null"),
+		Some(entry) => entry.to_string()
+	};
+
+	if symbols.is_empty() {
+		return format!("{{\n\t\"entry\": {},\n\t\"symbols\": []\n}}\n", entry)
+	}
+
+	format!("{{\n\t\"entry\": {},\n\t\"symbols\": [\n{}\n\t]\n}}\n", entry, symbols.join(",\n"))
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use assembler::Assembler;
+
+	#[test]
+	fn defines_are_absolute() {
+		let mut assembler = Assembler::new();
+		assembler.defines.push((String::from("size"), 3));
+
+		let program = assembler.assemble("-", String::from("\tr0 -> size\nend\n")).unwrap();
+		let symbols = symbols(&program);
+
+		assert_eq!(symbols.len(), 2);
+		assert_eq!((symbols[0].name.as_str(), symbols[0].section.as_str()), ("end", "text"));
+		assert_eq!((symbols[0].addr, symbols[0].size), (2, 0));
+		assert_eq!((symbols[1].name.as_str(), symbols[1].section.as_str()), ("size", ABS));
+		assert_eq!((symbols[1].addr, symbols[1].size), (3, 0));
+	}
+}
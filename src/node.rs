@@ -1,60 +1,273 @@
 use std::collections::HashMap;
 use std::mem;
 
+use loc::Loc;
 use cpu::{Opcode, Inst};
-use error::{ErrorId, Error, gen_error};
+use error::{ErrorId, Error};
 
 
+// words generated by a single node
+#[derive(Debug, Clone)]
+pub struct Record {
+	// source file and line of the node
+	pub file: usize,
+	pub line: usize,
+	pub section: usize,
+	// address within the section
+	pub addr: usize,
+	pub len: usize,
+	// whether the first word is an instruction
+	pub inst: bool
+}
+
+#[derive(Debug, Clone)]
+pub struct Section {
+	pub name: String,
+	pub binary: Vec<u32>
+}
+
+// address of a label within its section
+#[derive(Debug, Clone)]
+pub struct Label {
+	pub section: usize,
+	pub addr: usize
+}
+
+// word to which the address of a label is added
+#[derive(Debug, Clone)]
+pub struct Reloc {
+	pub section: usize,
+	pub addr: usize,
+	pub label: String,
+	// source location of the reference
+	pub loc: Loc
+}
+
 #[derive(Debug)]
 pub struct Program {
 	pub nodes: Vec<Node>,
-	// binary output
-	pub binary: Vec<u32>,
+	// source location of each node
+	pub locs: Vec<Loc>,
+	// sections in order of appearance
+	pub sections: Vec<Section>,
+	// current section
+	pub section: usize,
 	// list of labels
-	pub labels: HashMap<String, i32>,
+	pub labels: HashMap<String, Label>,
+	// labels exported from an object
+	pub globals: Vec<String>,
+	// `.global` directive of each exported label
+	pub global_locs: Vec<Loc>,
+	// labels imported into an object
+	pub externs: Vec<String>,
+	// `.extern` directive of each imported label
+	pub extern_locs: Vec<Loc>,
 	// labels to be filled
-	pub queue: HashMap<String, Vec<usize>>,
-	// current address
-	pub addr: usize
+	pub relocs: Vec<Reloc>,
+	// source location of the node being generated
+	pub loc: Loc,
+	// labels given a value outside the source, e.g. `-D`
+	pub defines: HashMap<String, u32>,
+	// register holding partial results of nested expressions, set by `.scratch`
+	pub scratch: Option<u8>,
+	// register `call` leaves the return address in and `ret` jumps to
+	pub link: u8,
+	// stack pointer of `push` and `pop`, the stack grows down one word per value
+	pub stack: u8,
+	// words generated by each node
+	pub records: Vec<Record>,
+	// address of each section in the binary
+	pub bases: Vec<usize>,
+	// binary output, sections placed one after another
+	pub binary: Vec<u32>
 }
 
 impl Program {
-	pub fn gen(mut self) -> Option<Vec<u32>> {
+	pub fn new(nodes: Vec<Node>, locs: Vec<Loc>) -> Program {
+		Program {
+			nodes,
+			locs,
+			sections: vec![Section {
+				name: String::from("text"),
+				binary: Vec::new()
+			}],
+			section: 0,
+			labels: HashMap::new(),
+			globals: Vec::new(),
+			global_locs: Vec::new(),
+			externs: Vec::new(),
+			extern_locs: Vec::new(),
+			relocs: Vec::new(),
+			loc: Loc::default(),
+			defines: HashMap::new(),
+			scratch: None,
+			link: 14,
+			stack: 13,
+			records: Vec::new(),
+			bases: Vec::new(),
+			binary: Vec::new()
+		}
+	}
+
+	// generates every node, the error is located at the node that failed
+	pub fn gen(&mut self) -> Result<(), Error> {
 		let mut nodes = vec![];
 		mem::swap(&mut nodes, &mut self.nodes);
 
-		for node in nodes {
-			if node.gen(&mut self) == false {
-				return None
+		let locs = self.locs.clone();
+
+		for (node, loc) in nodes.into_iter().zip(locs) {
+			self.loc = loc;
+
+			if let Err(id) = node.gen(self) {
+				return Err(Error {
+					id,
+					loc: self.loc.clone()
+				})
 			}
 		}
 
-		Some(self.binary)
+		self.layout();
+
+		Ok(())
+	}
+
+	// places the sections one after another and fills in defined labels
+	fn layout(&mut self) {
+		self.bases.clear();
+		self.binary.clear();
+
+		for section in &self.sections {
+			self.bases.push(self.binary.len());
+			self.binary.extend_from_slice(&section.binary);
+		}
+
+		for reloc in &self.relocs {
+			if let Some(label) = self.labels.get(&reloc.label) {
+				let addr = self.bases[reloc.section] + reloc.addr;
+				let value = (self.bases[label.section] + label.addr) as u32;
+
+				self.binary[addr] = self.binary[addr].wrapping_add(value);
+			}
+		}
+	}
+
+	// address of a label in the binary
+	pub fn label_addr(&self, label: &Label) -> usize {
+		self.bases[label.section] + label.addr
+	}
+
+	// references to labels that are not defined
+	pub fn unresolved(&self) -> Vec<&Reloc> {
+		self.relocs
+			.iter()
+			.filter(|reloc| !self.labels.contains_key(&reloc.label))
+			.collect()
+	}
+
+	// references to labels that are neither defined nor imported
+	pub fn undefined(&self) -> Vec<&Reloc> {
+		self.unresolved()
+			.into_iter()
+			.filter(|reloc| !self.externs.contains(&reloc.label))
+			.collect()
+	}
+
+	// exported labels that are not defined
+	pub fn undefined_globals(&self) -> Vec<(&String, &Loc)> {
+		self.globals
+			.iter()
+			.zip(&self.global_locs)
+			.filter(|(label, _)| !self.labels.contains_key(*label))
+			.collect()
+	}
+
+	// imported labels that are never referenced
+	pub fn unused_externs(&self) -> Vec<(&String, &Loc)> {
+		self.externs
+			.iter()
+			.zip(&self.extern_locs)
+			.filter(|(label, _)| !self.relocs.iter().any(|reloc| reloc.label == **label))
+			.collect()
+	}
+
+	// current address within the current section
+	fn addr(&self) -> usize {
+		self.sections[self.section].binary.len()
 	}
 
+	// the word is filled in by `layout` or the linker
+	// defines are known, a label may not have the name of one
 	fn get_iden(&mut self, iden: String, offset: usize) -> u32 {
-		match self.labels.get(&iden) {
+		if let Some(value) = self.defines.get(&iden) {
+			return *value
+		}
+
+		let reloc = Reloc {
+			section: self.section,
+			addr: self.addr() + offset,
+			label: iden,
+			loc: self.loc.clone()
+		};
+
+		self.relocs.push(reloc);
+
+		0
+	}
+
+	fn set_section(&mut self, name: String) {
+		match self.sections.iter().position(|section| section.name == name) {
+			Some(section) => self.section = section,
 			None => {
-				self.queue.entry(iden).or_insert(vec![]).push(self.addr + offset);
-				0
-			},
-			Some(num) => *num as u32
+				self.sections.push(Section {
+					name,
+					binary: Vec::new()
+				});
+
+				self.section = self.sections.len() - 1;
+			}
 		}
 	}
+
+	fn emit(&mut self, mut words: Vec<u32>, inst: bool) {
+		self.records.push(Record {
+			file: self.loc.file,
+			line: self.loc.line,
+			section: self.section,
+			addr: self.addr(),
+			len: words.len(),
+			inst
+		});
+
+		self.sections[self.section].binary.append(&mut words);
+	}
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Node {
 	Num(i32),
 	Iden(String),
 	Reg(u8),
 	Label(String),
+	Section(String),
+	Global(Vec<String>),
+	Extern(Vec<String>),
+	// `.reg` and `.unreg`, the lexer has already applied them
+	Alias(String, u8),
+	Unalias(String),
+	// `.scratch rN`, `None` for `.scratch _`
+	Scratch(Option<u8>),
+	// `.link rN`
+	Link(u8),
+	// `.stack rN`
+	Stack(u8),
+	// replaced by the nodes of the file while parsing
+	Include(String),
 	Empty,
 
 	Not(Box<Node>),
 	Neg(Box<Node>),
 	Rep(Box<Node>),
-	Mem(Box<Node>),
 
 	Or(Box<Node>, Box<Node>),
 	And(Box<Node>, Box<Node>),
@@ -77,10 +290,335 @@ pub enum Node {
 
 	To(Box<Node>, Box<Node>),
 	Cond(Box<Node>, Box<Node>),
+
+	// `jmp x`, `call x`, `ret`, `nop` and `halt`
+	Jump(Box<Node>),
+	Call(Box<Node>),
+	Ret,
+	Nop,
+	Halt,
+	// `push x, y` pushes `x` first, `pop x, y` pops `y` first
+	Push(Vec<Node>),
+	Pop(Vec<Node>),
+}
+
+// operator of a node and its operands
+enum Shape {
+	Unary(fn(Box<Node>) -> Node, Node),
+	Binary(fn(Box<Node>, Box<Node>) -> Node, Node, Node)
+}
+
+// register for a partial result, `target` when it may hold one
+fn pick(target: u8, own: bool, free: &[u8]) -> Result<(u8, Vec<u8>), ErrorId> {
+	if own {
+		return Ok((target, free.to_vec()))
+	}
+
+	match free.split_first() {
+		Some((&reg, rest)) => Ok((reg, rest.to_vec())),
+		None => Err(ErrorId::NoScratch)
+	}
+}
+
+// the scratch register unless it is `dest`
+// an error if the instruction reads it, its value would be lost
+fn free(dest: Option<u8>, nodes: &[&Node], inst: &Inst, program: &Program) -> Result<Vec<u8>, ErrorId> {
+	let free: Vec<u8> = program.scratch.into_iter().filter(|&reg| Some(reg) != dest).collect();
+
+	for &reg in &free {
+		if nodes.iter().any(|node| node.reads(reg)) || (inst.ce && inst.cond == reg) {
+			return Err(ErrorId::ScratchRead(reg))
+		}
+	}
+
+	Ok(free)
+}
+
+fn nor(left: Box<Node>, right: Box<Node>) -> Node {
+	Node::Not(Box::new(Node::Or(left, right)))
+}
+
+fn nand(left: Box<Node>, right: Box<Node>) -> Node {
+	Node::Not(Box::new(Node::And(left, right)))
+}
+
+fn xnor(left: Box<Node>, right: Box<Node>) -> Node {
+	Node::Not(Box::new(Node::Xor(left, right)))
 }
 
 impl Node {
-	fn gen_src0(self, program: &mut Program, inst: &mut Inst) -> bool {
+	// operand an instruction reads without computing it
+	fn is_leaf(&self) -> bool {
+		matches!(*self, Node::Num(_) | Node::Reg(_) | Node::Iden(_))
+	}
+
+	fn reads(&self, reg: u8) -> bool {
+		match *self {
+			Node::Reg(src) => src == reg,
+			Node::Not(ref node) | Node::Neg(ref node) | Node::Rep(ref node) |
+			Node::Mem8(ref node) | Node::Mem16(ref node) | Node::Mem32(ref node) => node.reads(reg),
+			Node::Or(ref left, ref right) | Node::And(ref left, ref right) | Node::Xor(ref left, ref right) |
+			Node::Add(ref left, ref right) | Node::Sub(ref left, ref right) | Node::Sl(ref left, ref right) |
+			Node::Sr(ref left, ref right) | Node::Mul(ref left, ref right) | Node::Div(ref left, ref right) |
+			Node::Eql(ref left, ref right) | Node::Lt(ref left, ref right) => left.reads(reg) || right.reads(reg),
+			_ => false
+		}
+	}
+
+	// whether a single instruction computes the node
+	fn is_flat(&self) -> bool {
+		match *self {
+			Node::Not(box Node::Or(ref left, ref right)) |
+			Node::Not(box Node::And(ref left, ref right)) |
+			Node::Not(box Node::Xor(ref left, ref right)) => left.is_leaf() && right.is_leaf(),
+			Node::Not(ref node) | Node::Neg(ref node) | Node::Rep(ref node) |
+			Node::Mem8(ref node) | Node::Mem16(ref node) | Node::Mem32(ref node) => node.is_leaf(),
+			Node::Or(ref left, ref right) | Node::And(ref left, ref right) | Node::Xor(ref left, ref right) |
+			Node::Add(ref left, ref right) | Node::Sub(ref left, ref right) | Node::Sl(ref left, ref right) |
+			Node::Sr(ref left, ref right) | Node::Mul(ref left, ref right) | Node::Div(ref left, ref right) |
+			Node::Lt(ref left, ref right) => left.is_leaf() && right.is_leaf(),
+			_ => self.is_leaf()
+		}
+	}
+
+	fn shape(self) -> Option<Shape> {
+		Some(match self {
+			Node::Not(box Node::Or(box left, box right)) => Shape::Binary(nor, left, right),
+			Node::Not(box Node::And(box left, box right)) => Shape::Binary(nand, left, right),
+			Node::Not(box Node::Xor(box left, box right)) => Shape::Binary(xnor, left, right),
+			Node::Not(box node) => Shape::Unary(Node::Not, node),
+			Node::Neg(box node) => Shape::Unary(Node::Neg, node),
+			Node::Rep(box node) => Shape::Unary(Node::Rep, node),
+			Node::Mem8(box node) => Shape::Unary(Node::Mem8, node),
+			Node::Mem16(box node) => Shape::Unary(Node::Mem16, node),
+			Node::Mem32(box node) => Shape::Unary(Node::Mem32, node),
+			Node::Or(box left, box right) => Shape::Binary(Node::Or, left, right),
+			Node::And(box left, box right) => Shape::Binary(Node::And, left, right),
+			Node::Xor(box left, box right) => Shape::Binary(Node::Xor, left, right),
+			Node::Add(box left, box right) => Shape::Binary(Node::Add, left, right),
+			Node::Sub(box left, box right) => Shape::Binary(Node::Sub, left, right),
+			Node::Sl(box left, box right) => Shape::Binary(Node::Sl, left, right),
+			Node::Sr(box left, box right) => Shape::Binary(Node::Sr, left, right),
+			Node::Mul(box left, box right) => Shape::Binary(Node::Mul, left, right),
+			Node::Div(box left, box right) => Shape::Binary(Node::Div, left, right),
+			Node::Lt(box left, box right) => Shape::Binary(Node::Lt, left, right),
+			_ => return None
+		})
+	}
+
+	// emits instructions leaving the node in `target`, the last one is `last`
+	// `own` lets `target` hold partial results, `free` registers may be overwritten
+	fn lower(self, target: u8, own: bool, free: &[u8], last: Inst, program: &mut Program) -> Result<(), ErrorId> {
+		if self.is_flat() {
+			let mut inst = last;
+			let node = Node::To(Box::new(Node::Reg(target)), Box::new(self));
+
+			node.gen_uncond(program, &mut inst)?;
+
+			program.emit(inst.gen(), true);
+
+			return Ok(())
+		}
+
+		let flat = match self.shape() {
+			None => return Err(ErrorId::InvalidInstruction),
+			Some(Shape::Unary(op, node)) => {
+				let (reg, rest) = pick(target, own, free)?;
+
+				node.lower(reg, true, &rest, Inst::new(), program)?;
+
+				op(Box::new(Node::Reg(reg)))
+			},
+			Some(Shape::Binary(op, left, right)) => match (left.is_leaf(), right.is_leaf()) {
+				(false, true) => {
+					let (reg, rest) = pick(target, own && !right.reads(target), free)?;
+
+					left.lower(reg, true, &rest, Inst::new(), program)?;
+
+					op(Box::new(Node::Reg(reg)), Box::new(right))
+				},
+				(true, false) => {
+					let (reg, rest) = pick(target, own && !left.reads(target), free)?;
+
+					right.lower(reg, true, &rest, Inst::new(), program)?;
+
+					op(Box::new(left), Box::new(Node::Reg(reg)))
+				},
+				_ => {
+					// the side reading `target` goes first, before `target` is overwritten
+					let swap = right.reads(target) && !left.reads(target);
+					let (first, second) = if swap { (right, left) } else { (left, right) };
+
+					let (first_reg, rest) = pick(target, own && !second.reads(target), free)?;
+
+					first.lower(first_reg, true, &rest, Inst::new(), program)?;
+
+					let (second_reg, rest) = pick(target, own && first_reg != target, &rest)?;
+
+					second.lower(second_reg, true, &rest, Inst::new(), program)?;
+
+					let (first, second) = (Box::new(Node::Reg(first_reg)), Box::new(Node::Reg(second_reg)));
+
+					if swap {
+						op(second, first)
+					} else {
+						op(first, second)
+					}
+				}
+			}
+		};
+
+		flat.lower(target, own, free, last, program)
+	}
+
+	// generates an instruction, nested expressions are first computed into registers
+	// instructions computing partial results are unconditional and only write the
+	// scratch register or, when unconditional, the destination
+	fn gen_inst(self, program: &mut Program, inst: Inst) -> Result<(), ErrorId> {
+		match self {
+			Node::To(box Node::Reg(dest), box right) => {
+				if right.is_flat() {
+					return Node::To(Box::new(Node::Reg(dest)), Box::new(right)).gen_emit(program, inst)
+				}
+
+				let free = free(Some(dest), &[&right], &inst, program)?;
+
+				// a partial result in `pc` would jump to it
+				let own = !inst.ce && dest != 15;
+
+				right.lower(dest, own, &free, inst, program)
+			},
+			Node::To(box left, box right) => {
+				let (op, addr): (fn(Box<Node>) -> Node, Node) = match left {
+					Node::Mem8(box addr) => (Node::Mem8, addr),
+					Node::Mem16(box addr) => (Node::Mem16, addr),
+					Node::Mem32(box addr) => (Node::Mem32, addr),
+					_ => return Err(ErrorId::InvalidInstruction)
+				};
+
+				if addr.is_leaf() && right.is_leaf() {
+					return Node::To(Box::new(op(Box::new(addr))), Box::new(right)).gen_emit(program, inst)
+				}
+
+				let mut free = free(None, &[&addr, &right], &inst, program)?;
+
+				let mut operands = vec![];
+
+				for node in [addr, right] {
+					if node.is_leaf() {
+						operands.push(node);
+						continue
+					}
+
+					let (reg, rest) = pick(0, false, &free)?;
+
+					node.lower(reg, true, &rest, Inst::new(), program)?;
+
+					free = rest;
+					operands.push(Node::Reg(reg));
+				}
+
+				let right = operands.pop().unwrap();
+				let addr = operands.pop().unwrap();
+
+				Node::To(Box::new(op(Box::new(addr))), Box::new(right)).gen_emit(program, inst)
+			},
+			Node::Jump(box target) => Node::To(Box::new(Node::Reg(15)), Box::new(target)).gen_inst(program, inst),
+			// `link -> pc + n` where `pc + n` is past the jump, then the jump
+			Node::Call(box target) => {
+				let link = program.link;
+
+				// the jump would be skipped once `link` is written
+				if !target.is_leaf() || (inst.ce && inst.cond == link) {
+					return Err(ErrorId::InvalidInstruction)
+				}
+
+				let size = match target {
+					Node::Reg(_) => 1,
+					_ => 2
+				};
+
+				let ret = Node::Add(Box::new(Node::Reg(15)), Box::new(Node::Num(size)));
+
+				Node::To(Box::new(Node::Reg(link)), Box::new(ret)).gen_emit(program, inst.clone())?;
+
+				Node::To(Box::new(Node::Reg(15)), Box::new(target)).gen_emit(program, inst)
+			},
+			Node::Ret => {
+				let link = program.link;
+
+				Node::To(Box::new(Node::Reg(15)), Box::new(Node::Reg(link))).gen_emit(program, inst)
+			},
+			// `sp -> sp - 1` then `m32(sp) -> x` for every value
+			Node::Push(nodes) => {
+				let sp = program.stack;
+
+				// the condition would change with `sp`
+				if inst.ce && inst.cond == sp {
+					return Err(ErrorId::InvalidInstruction)
+				}
+
+				for node in nodes {
+					let dec = Node::Sub(Box::new(Node::Reg(sp)), Box::new(Node::Num(1)));
+
+					Node::To(Box::new(Node::Reg(sp)), Box::new(dec)).gen_emit(program, inst.clone())?;
+
+					let top = Node::Mem32(Box::new(Node::Reg(sp)));
+
+					Node::To(Box::new(top), Box::new(node)).gen_inst(program, inst.clone())?;
+				}
+
+				Ok(())
+			},
+			// `x -> m32(sp)` then `sp -> sp + 1` for every register in reverse
+			Node::Pop(nodes) => {
+				let sp = program.stack;
+
+				for node in nodes.into_iter().rev() {
+					match node {
+						// the condition would change before `sp` does
+						Node::Reg(reg) if reg != sp && !(inst.ce && inst.cond == reg) => {
+							let top = Node::Mem32(Box::new(Node::Reg(sp)));
+
+							Node::To(Box::new(Node::Reg(reg)), Box::new(top)).gen_emit(program, inst.clone())?;
+						},
+						_ => return Err(ErrorId::InvalidInstruction)
+					}
+
+					let inc = Node::Add(Box::new(Node::Reg(sp)), Box::new(Node::Num(1)));
+
+					Node::To(Box::new(Node::Reg(sp)), Box::new(inc)).gen_emit(program, inst.clone())?;
+				}
+
+				Ok(())
+			},
+			// a move writing no register
+			Node::Nop => {
+				program.emit(inst.gen(), true);
+
+				Ok(())
+			},
+			Node::Halt => {
+				let mut inst = inst;
+				inst.opcode = Opcode::Done;
+
+				program.emit(inst.gen(), true);
+
+				Ok(())
+			},
+			node => node.gen_emit(program, inst)
+		}
+	}
+
+	fn gen_emit(self, program: &mut Program, mut inst: Inst) -> Result<(), ErrorId> {
+		self.gen_uncond(program, &mut inst)?;
+
+		program.emit(inst.gen(), true);
+
+		Ok(())
+	}
+
+	fn gen_src0(self, program: &mut Program, inst: &mut Inst) -> Result<(), ErrorId> {
 		match self {
 			Node::Num(num) => {
 				inst.i0 = true;
@@ -93,13 +631,13 @@ impl Node {
 				inst.i0 = true;
 				inst.imm0 = program.get_iden(iden, 1);
 			},
-			_ => return false
+			_ => return Err(ErrorId::InvalidInstruction)
 		}
 
-		true
+		Ok(())
 	}
 
-	fn gen_src1(self, program: &mut Program, inst: &mut Inst) -> bool {
+	fn gen_src1(self, program: &mut Program, inst: &mut Inst) -> Result<(), ErrorId> {
 		match self {
 			Node::Num(num) => {
 				inst.i1 = true;
@@ -109,16 +647,19 @@ impl Node {
 				inst.src1 = reg;
 			},
 			Node::Iden(iden) => {
+				// imm1 follows imm0
+				let offset = 1 + inst.i0 as usize;
+
 				inst.i1 = true;
-				inst.imm1 = program.get_iden(iden, 1);
+				inst.imm1 = program.get_iden(iden, offset);
 			},
-			_ => return false
+			_ => return Err(ErrorId::InvalidInstruction)
 		}
 
-		true
+		Ok(())
 	}
 
-	fn gen_uncond(self, program: &mut Program, inst: &mut Inst) -> bool {
+	fn gen_uncond(self, program: &mut Program, inst: &mut Inst) -> Result<(), ErrorId> {
 		match self {
 			// gen instructions
 			Node::To(box left, box right) => {
@@ -163,372 +704,391 @@ impl Node {
 								Node::Or(box left, box right) => {
 									inst.opcode = Opcode::Nor;
 
-									if !left.gen_src0(program, inst) {
-										return false
-									}
+									left.gen_src0(program, inst)?;
 
-									if !right.gen_src1(program, inst) {
-										return false
-									}
+									right.gen_src1(program, inst)?;
 								},
 								Node::And(box left, box right) => {
 									inst.opcode = Opcode::Nand;
 
-									if !left.gen_src0(program, inst) {
-										return false
-									}
+									left.gen_src0(program, inst)?;
 
-									if !right.gen_src1(program, inst) {
-										return false
-									}
+									right.gen_src1(program, inst)?;
 								},
 								Node::Xor(box left, box right) => {
 									inst.opcode = Opcode::Xnor;
 
-									if !left.gen_src0(program, inst) {
-										return false
-									}
+									left.gen_src0(program, inst)?;
 
-									if !right.gen_src1(program, inst) {
-										return false
-									}
+									right.gen_src1(program, inst)?;
 								},
-								_ => return false
+								_ => return Err(ErrorId::InvalidInstruction)
 							},
 							Node::Neg(box node) => {
 								inst.opcode = Opcode::Neg;
 
-								if !node.gen_src0(program, inst) {
-									return false
-								}
+								node.gen_src0(program, inst)?;
 							},
 							Node::Rep(box node) => {
 								inst.opcode = Opcode::Rep;
 
-								if !node.gen_src0(program, inst) {
-									return false
-								}
+								node.gen_src0(program, inst)?;
 							},
 							Node::Or(box left, box right) => {
 								inst.opcode = Opcode::Or;
 
-								if !left.gen_src0(program, inst) {
-									return false
-								}
+								left.gen_src0(program, inst)?;
 
-								if !right.gen_src1(program, inst) {
-									return false
-								}
+								right.gen_src1(program, inst)?;
 							},
 							Node::And(box left, box right) => {
 								inst.opcode = Opcode::And;
 
-								if !left.gen_src0(program, inst) {
-									return false
-								}
+								left.gen_src0(program, inst)?;
 
-								if !right.gen_src1(program, inst) {
-									return false
-								}
+								right.gen_src1(program, inst)?;
 							},
 							Node::Xor(box left, box right) => {
 								inst.opcode = Opcode::Xor;
 
-								if !left.gen_src0(program, inst) {
-									return false
-								}
+								left.gen_src0(program, inst)?;
 
-								if !right.gen_src1(program, inst) {
-									return false
-								}
+								right.gen_src1(program, inst)?;
 							},
 							Node::Add(box left, box right) => {
 								inst.opcode = Opcode::Add;
 
-								if !left.gen_src0(program, inst) {
-									return false
-								}
+								left.gen_src0(program, inst)?;
 
-								if !right.gen_src1(program, inst) {
-									return false
-								}
+								right.gen_src1(program, inst)?;
 							},
 							Node::Sub(box left, box right) => {
 								inst.opcode = Opcode::Sub;
 
-								if !left.gen_src0(program, inst) {
-									return false
-								}
+								left.gen_src0(program, inst)?;
 
-								if !right.gen_src1(program, inst) {
-									return false
-								}
+								right.gen_src1(program, inst)?;
 							},
 							Node::Lt(box left, box right) => {
 								inst.opcode = Opcode::Lt;
 
-								if !left.gen_src0(program, inst) {
-									return false
-								}
+								left.gen_src0(program, inst)?;
 
-								if !right.gen_src1(program, inst) {
-									return false
-								}
+								right.gen_src1(program, inst)?;
 							},
 							Node::Sl(box left, box right) => {
 								inst.opcode = Opcode::Sl;
 
-								if !left.gen_src0(program, inst) {
-									return false
-								}
+								left.gen_src0(program, inst)?;
 
-								if !right.gen_src1(program, inst) {
-									return false
-								}
+								right.gen_src1(program, inst)?;
 							},
 							Node::Sr(box left, box right) => {
 								inst.opcode = Opcode::Sr;
 
-								if !left.gen_src0(program, inst) {
-									return false
-								}
+								left.gen_src0(program, inst)?;
 
-								if !right.gen_src1(program, inst) {
-									return false
-								}
+								right.gen_src1(program, inst)?;
 							},
 							Node::Mul(box left, box right) => {
 								inst.opcode = Opcode::Mul;
 
-								if !left.gen_src0(program, inst) {
-									return false
-								}
+								left.gen_src0(program, inst)?;
 
-								if !right.gen_src1(program, inst) {
-									return false
-								}
+								right.gen_src1(program, inst)?;
 							},
 							Node::Div(box left, box right) => {
 								inst.opcode = Opcode::Div;
 
-								if !left.gen_src0(program, inst) {
-									return false
-								}
+								left.gen_src0(program, inst)?;
 
-								if !right.gen_src1(program, inst) {
-									return false
-								}
+								right.gen_src1(program, inst)?;
 							},
 							Node::Mem8(box node) => {
 								inst.opcode = Opcode::Lod8;
 
-								match node {
-									Node::Num(num) => {
-										inst.i0 = true;
-										inst.imm0 = num as u32;
-									},
-									Node::Reg(reg) => {
-										inst.src0 = reg;
-									},
-									_ => return false
-								}
+								node.gen_src0(program, inst)?;
 							},
 							Node::Mem16(box node) => {
 								inst.opcode = Opcode::Lod16;
 
-								match node {
-									Node::Num(num) => {
-										inst.i0 = true;
-										inst.imm0 = num as u32;
-									},
-									Node::Reg(reg) => {
-										inst.src0 = reg;
-									},
-									_ => return false
-								}
+								node.gen_src0(program, inst)?;
 							},
 							Node::Mem32(box node) => {
 								inst.opcode = Opcode::Lod32;
 
-								match node {
-									Node::Num(num) => {
-										inst.i0 = true;
-										inst.imm0 = num as u32;
-									},
-									Node::Reg(reg) => {
-										inst.src0 = reg;
-									},
-									_ => return false
-								}
+								node.gen_src0(program, inst)?;
 							},
-							_ => return false
+							_ => return Err(ErrorId::InvalidInstruction)
 						}
 					},
 					Node::Mem8(box node) => {
 						inst.opcode = Opcode::Sto8;
 
-						match node {
-							Node::Num(num) => {
-								inst.i0 = true;
-								inst.imm0 = num as u32;
-							},
-							Node::Reg(reg) => {
-								inst.src0 = reg;
-							},
-							_ => return false
-						}
+						node.gen_src0(program, inst)?;
 
-						match right {
-							Node::Num(num) => {
-								inst.i1 = true;
-								inst.imm1 = num as u32;
-							},
-							Node::Reg(reg) => {
-								inst.src1 = reg;
-							},
-							_ => return false
-						}
+						right.gen_src1(program, inst)?;
 					},
 					Node::Mem16(box node) => {
 						inst.opcode = Opcode::Sto16;
 
-						match node {
-							Node::Num(num) => {
-								inst.i0 = true;
-								inst.imm0 = num as u32;
-							},
-							Node::Reg(reg) => {
-								inst.src0 = reg;
-							},
-							_ => return false
-						}
+						node.gen_src0(program, inst)?;
 
-						match right {
-							Node::Num(num) => {
-								inst.i1 = true;
-								inst.imm1 = num as u32;
-							},
-							Node::Reg(reg) => {
-								inst.src1 = reg;
-							},
-							_ => return false
-						}
+						right.gen_src1(program, inst)?;
 					},
 					Node::Mem32(box node) => {
 						inst.opcode = Opcode::Sto32;
 
-						match node {
-							Node::Num(num) => {
-								inst.i0 = true;
-								inst.imm0 = num as u32;
-							},
-							Node::Reg(reg) => {
-								inst.src0 = reg;
-							},
-							_ => return false
-						}
+						node.gen_src0(program, inst)?;
 
-						match right {
-							Node::Num(num) => {
-								inst.i1 = true;
-								inst.imm1 = num as u32;
-							},
-							Node::Reg(reg) => {
-								inst.src1 = reg;
-							},
-							_ => return false
-						}
+						right.gen_src1(program, inst)?;
 					},
-					_ => return false
+					_ => return Err(ErrorId::InvalidInstruction)
 				}
 			},
-			_ => return false
+			_ => return Err(ErrorId::InvalidInstruction)
 		}
 
-		true
+		Ok(())
 	}
 
-	fn gen(self, program: &mut Program) -> bool {
+	fn gen(self, program: &mut Program) -> Result<(), ErrorId> {
 		match self {
 			// immediate number
-			Node::Num(num) => {
-				program.binary.push(num as u32);
-				program.addr += 1;
-			},
+			Node::Num(num) => program.emit(vec![num as u32], false),
 			// immediate iden
 			Node::Iden(iden) => {
 				let iden_u32 = program.get_iden(iden, 0);
-				program.binary.push(iden_u32);
-
-				program.addr += 1;
+				program.emit(vec![iden_u32], false);
 			},
 			Node::Label(label) => {
-				match program.queue.remove(&label) {
-					None => (),
-					Some(addrs) => {
-						for addr in addrs {
-							program.binary[addr] = program.addr as u32
-						}
-					}
+				// `-D` defines are labels too
+				if program.labels.contains_key(&label) || program.defines.contains_key(&label) {
+					return Err(ErrorId::DuplicateLabel(label))
 				}
 
-				program.labels.insert(label, program.addr as i32);
+				let addr = program.addr();
+
+				program.labels.insert(label, Label {
+					section: program.section,
+					addr
+				});
+
+				program.emit(vec![], false);
+			},
+			Node::Section(name) => program.set_section(name),
+			Node::Global(labels) => {
+				let loc = program.loc.clone();
+
+				program.global_locs.extend(labels.iter().map(|_| loc.clone()));
+				program.globals.extend(labels);
+			},
+			Node::Extern(labels) => {
+				let loc = program.loc.clone();
+
+				program.extern_locs.extend(labels.iter().map(|_| loc.clone()));
+				program.externs.extend(labels);
 			},
+			Node::Alias(..) | Node::Unalias(_) => (),
+			Node::Scratch(reg) => program.scratch = reg,
+			Node::Link(reg) => program.link = reg,
+			Node::Stack(reg) => program.stack = reg,
 			// negative numbers
 			Node::Neg(box node) => match node {
-				Node::Num(num) => {
-					program.binary.push((-num) as u32);
-					program.addr += 1;
-				},
-				_ => return false
+				Node::Num(num) => program.emit(vec![num.wrapping_neg() as u32], false),
+				_ => return Err(ErrorId::InvalidInstruction)
 			},
 
 			Node::Cond(box node, box cond) => {
 				let mut inst = Inst::new();
 
 				match cond {
-					Node::Not(box node) => match node {
-						Node::Eql(box left, box right) => match left {
-							Node::Num(0) => match right {
-								Node::Reg(reg) => {
-									inst.ce = true;
-									inst.cond = reg;
-								},
-								_ => return false
+					Node::Not(box Node::Eql(box left, box right)) => match left {
+						Node::Num(0) => match right {
+							Node::Reg(reg) => {
+								inst.ce = true;
+								inst.cond = reg;
 							},
-							Node::Reg(reg) => match right {
-								Node::Num(0) => {
-									inst.ce = true;
-									inst.cond = reg;
-								},
-								_ => return false
-							}
-							_ => return false
+							_ => return Err(ErrorId::InvalidInstruction)
 						},
-						_ => return false
+						Node::Reg(reg) => match right {
+							Node::Num(0) => {
+								inst.ce = true;
+								inst.cond = reg;
+							},
+							_ => return Err(ErrorId::InvalidInstruction)
+						}
+						_ => return Err(ErrorId::InvalidInstruction)
 					},
-					_ => return false
+					_ => return Err(ErrorId::InvalidInstruction)
 				}
 
-				node.gen_uncond(program, &mut inst);
+				return node.gen_inst(program, inst)
+			},
+			_ => return self.gen_inst(program, Inst::new())
+		}
+		Ok(())
+	}
+}
 
-				let mut inst_raw = inst.gen();
-				program.addr += inst_raw.len();
-				program.binary.append(&mut inst_raw);
+#[cfg(test)]
+mod tests {
+	use assembler::{assemble, Assembler};
+	use emu::Cpu;
+	use error::ErrorId;
 
-			},
-			_ => {
-				let mut inst = Inst::new();
+	fn run(source: &str) -> Cpu {
+		let assembly = assemble(source).unwrap();
+		let mut cpu = Cpu::new(assembly.binary(), 1024);
 
-				if !self.gen_uncond(program, &mut inst) {
-					return false
-				}
+		cpu.run(1000).unwrap();
+		cpu
+	}
 
-				let mut inst_raw = inst.gen();
-				program.addr += inst_raw.len();
-				program.binary.append(&mut inst_raw);
-			}
+	#[test]
+	fn conditional_invalid_instruction_fails() {
+		let errors = assemble("\t5 -> r1 ? !(r2 = 0)\n").unwrap_err();
+
+		assert_eq!(errors[0].id, ErrorId::InvalidInstruction);
+	}
+
+	// `r1 - r2` is the `halt` before `target`
+	#[test]
+	fn nested_pc_expression_does_not_jump_to_partial_result() {
+		let cpu = run(".scratch r12
+	r1 -> target
+	r2 -> 1
+	r3 -> 1
+	r15 -> (r1 - r2) + r3
+	halt
+target
+	r5 -> 1
+	halt
+");
+
+		assert_eq!(cpu.regs[5], 1);
+	}
+
+	#[test]
+	fn jmp_to_nested_target() {
+		let cpu = run(".scratch r12
+	r1 -> target
+	r2 -> 1
+	jmp (r1 - r2) + 1
+	halt
+target
+	r5 -> 1
+	halt
+");
+
+		assert_eq!(cpu.regs[5], 1);
+	}
+
+	#[test]
+	fn label_named_like_a_define_is_an_error() {
+		let mut assembler = Assembler::new();
+		assembler.defines.push((String::from("x"), 5));
+
+		let errors = assembler.assemble("test.jasm", String::from("\tr0 -> x\nx\n\thalt\n")).unwrap_err();
+
+		assert_eq!(errors[0].id, ErrorId::DuplicateLabel(String::from("x")));
+		assert_eq!(errors[0].loc.line, 2);
+	}
+
+	#[test]
+	fn duplicate_label_is_an_error() {
+		let errors = assemble("a\n\tr0 -> 1\na\n").unwrap_err();
+
+		assert_eq!(errors[0].id, ErrorId::DuplicateLabel(String::from("a")));
+		assert_eq!(errors[0].loc.line, 3);
+	}
+
+	#[test]
+	fn undefined_global_is_reported_at_its_directive() {
+		let errors = assemble("\tr0 -> 1\n.global a\n").unwrap_err();
+
+		assert_eq!(errors[0].id, ErrorId::UndefinedGlobal(String::from("a")));
+		assert_eq!(errors[0].loc.line, 2);
+	}
+
+	#[test]
+	fn unused_extern_keeps_its_directive() {
+		let assembly = assemble("\tr0 -> 1\n.extern a\n").unwrap();
+		let unused = assembly.program.unused_externs();
+
+		assert_eq!(unused.len(), 1);
+		assert_eq!(unused[0].0, "a");
+		assert_eq!(unused[0].1.line, 2);
+	}
+
+	#[test]
+	fn conditional_push() {
+		let cpu = run("\tsp -> 100\n\tr1 -> 7\n\tpush r1, r1 ? !(r2 = 0)\n\tr2 -> 1\n\tpush r1 ? !(r2 = 0)\n\thalt\n");
+
+		assert_eq!(cpu.regs[13], 99);
+		assert_eq!(cpu.mem[99], 7);
+	}
+
+	#[test]
+	fn data_word_boundaries() {
+		let assembly = assemble("\t-2147483648\n\t2147483648\n\t4294967295\n\t-4294967295\n\t-1\n").unwrap();
+
+		assert_eq!(assembly.binary(), [0x80000000, 0x80000000, 0xffffffff, 1, 0xffffffff]);
+	}
+
+	#[test]
+	fn nested_pc_expression_needs_scratch() {
+		let errors = assemble("\tr15 -> (r1 - r2) + r3\n").unwrap_err();
+
+		assert_eq!(errors[0].id, ErrorId::NoScratch);
+	}
+
+	#[test]
+	fn scratch_does_not_carry_into_the_next_file() {
+		let errors = Assembler::new()
+			.assemble_all(vec![
+				(String::from("a"), String::from(".scratch r12\n")),
+				(String::from("b"), String::from("\tr15 -> (r1 - r2) + r3\n"))
+			])
+			.unwrap_err();
+
+		assert_eq!(errors[0].id, ErrorId::NoScratch);
+		assert_eq!(errors[0].loc.file, 1);
+	}
+
+	// a load computes the address into its destination
+	#[test]
+	fn load_base_offset() {
+		let assembly = assemble("\tr0 -> m32(r4 + 8)\n").unwrap();
+
+		assert_eq!(assembly.binary(), [0x24000046, 8, 0x6c000004]);
+	}
+
+	// a store computes the address into the scratch register
+	#[test]
+	fn store_base_offset() {
+		let assembly = assemble(".scratch r12\n\tm32(r4 + 8) -> r1\n").unwrap();
+
+		assert_eq!(assembly.binary(), [0x2400c046, 8, 0x600001c0]);
+	}
+
+	#[test]
+	fn store_base_offset_needs_scratch() {
+		for source in ["\tm32(r4 + 8) -> r1\n", ".scratch r12\n\tm32(r4 + 8) -> r1 + 1\n"] {
+			let errors = assemble(source).unwrap_err();
+
+			assert_eq!(errors[0].id, ErrorId::NoScratch);
 		}
-		true
+	}
+
+	#[test]
+	fn load_label_address() {
+		let assembly = assemble("\tr0 -> m32(table)\ntable\n\t5\n").unwrap();
+
+		assert_eq!(assembly.binary(), [0x6c000005, 2, 5]);
+
+		let cpu = run("\tr4 -> table\n\tr0 -> m32(table)\n\tr1 -> m32(r4 + 1)\n\thalt\ntable\n\t5\n\t6\n");
+
+		assert_eq!(cpu.regs[..2], [5, 6]);
 	}
 }
@@ -0,0 +1,321 @@
+use loc::Loc;
+use node::{Program, Section, Reloc};
+
+
+// JiftCPU object file, version 2
+//
+// all numbers are little endian u32, strings are a length followed by UTF-8
+//
+// "JOBJ" version
+// section count, (name, word count, words...)*
+// symbol count, (name, section, addr, global)*
+// import count, (name)*
+// reloc count, (section, addr, label)*
+const MAGIC: &[u8; 4] = b"JOBJ";
+const VERSION: u32 = 2;
+
+// label defined in an object
+#[derive(Debug, Clone)]
+pub struct Symbol {
+	pub name: String,
+	pub section: usize,
+	// address within the section
+	pub addr: usize,
+	// visible to other objects
+	pub global: bool
+}
+
+#[derive(Debug, Clone)]
+pub struct Object {
+	pub sections: Vec<Section>,
+	// labels defined in this object
+	pub symbols: Vec<Symbol>,
+	// labels declared `.extern`
+	pub imports: Vec<String>,
+	// every label reference, the words hold the addend
+	pub relocs: Vec<Reloc>
+}
+
+impl Object {
+	// must be called after `Program::gen`
+	pub fn new(program: &Program) -> Object {
+		let mut symbols: Vec<Symbol> = program.labels
+			.iter()
+			.map(|(name, label)| Symbol {
+				name: name.clone(),
+				section: label.section,
+				addr: label.addr,
+				global: program.globals.contains(name)
+			})
+			.collect();
+
+		symbols.sort_by(|a, b| a.name.cmp(&b.name));
+
+		let mut imports = program.externs.clone();
+
+		imports.sort();
+		imports.dedup();
+
+		Object {
+			sections: program.sections.clone(),
+			symbols,
+			imports,
+			relocs: program.relocs.clone()
+		}
+	}
+
+	pub fn globals(&self) -> impl Iterator<Item = &Symbol> {
+		self.symbols.iter().filter(|symbol| symbol.global)
+	}
+
+	// imported labels that are referenced
+	pub fn needed(&self) -> impl Iterator<Item = &String> {
+		self.imports
+			.iter()
+			.filter(move |label| self.relocs.iter().any(|reloc| reloc.label == **label))
+	}
+
+	pub fn find(&self, label: &str) -> Option<&Symbol> {
+		self.symbols.iter().find(|symbol| symbol.name == label)
+	}
+
+	pub fn to_bytes(&self) -> Vec<u8> {
+		let mut writer = Writer { bytes: MAGIC.to_vec() };
+
+		writer.num(VERSION as usize);
+
+		writer.num(self.sections.len());
+
+		for section in &self.sections {
+			writer.string(&section.name);
+			writer.num(section.binary.len());
+
+			for word in &section.binary {
+				writer.word(*word);
+			}
+		}
+
+		writer.num(self.symbols.len());
+
+		for symbol in &self.symbols {
+			writer.string(&symbol.name);
+			writer.num(symbol.section);
+			writer.num(symbol.addr);
+			writer.num(symbol.global as usize);
+		}
+
+		writer.num(self.imports.len());
+
+		for name in &self.imports {
+			writer.string(name);
+		}
+
+		writer.num(self.relocs.len());
+
+		for reloc in &self.relocs {
+			writer.num(reloc.section);
+			writer.num(reloc.addr);
+			writer.string(&reloc.label);
+		}
+
+		writer.bytes
+	}
+
+	// `None` if the bytes are not a valid object
+	pub fn from_bytes(bytes: &[u8]) -> Option<Object> {
+		if bytes.len() < 4 || &bytes[..4] != MAGIC {
+			return None
+		}
+
+		let mut reader = Reader { bytes, pos: 4 };
+
+		if reader.num()? != VERSION as usize {
+			return None
+		}
+
+		let mut sections = vec![];
+
+		for _ in 0..reader.num()? {
+			let name = reader.string()?;
+			let mut binary = vec![];
+
+			for _ in 0..reader.num()? {
+				binary.push(reader.word()?);
+			}
+
+			sections.push(Section { name, binary });
+		}
+
+		let mut symbols = vec![];
+
+		for _ in 0..reader.num()? {
+			let symbol = Symbol {
+				name: reader.string()?,
+				section: reader.num()?,
+				addr: reader.num()?,
+				global: reader.num()? != 0
+			};
+
+			if symbol.section >= sections.len() || symbol.addr > sections[symbol.section].binary.len() {
+				return None
+			}
+
+			symbols.push(symbol);
+		}
+
+		let mut imports = vec![];
+
+		for _ in 0..reader.num()? {
+			imports.push(reader.string()?);
+		}
+
+		let mut relocs = vec![];
+
+		for _ in 0..reader.num()? {
+			let reloc = Reloc {
+				section: reader.num()?,
+				addr: reader.num()?,
+				label: reader.string()?,
+				loc: Loc::default()
+			};
+
+			if reloc.section >= sections.len() || reloc.addr >= sections[reloc.section].binary.len() {
+				return None
+			}
+
+			relocs.push(reloc);
+		}
+
+		if reader.pos != bytes.len() {
+			return None
+		}
+
+		Some(Object {
+			sections,
+			symbols,
+			imports,
+			relocs
+		})
+	}
+}
+
+pub struct Writer {
+	pub bytes: Vec<u8>
+}
+
+impl Writer {
+	pub fn word(&mut self, word: u32) {
+		self.bytes.extend_from_slice(&word.to_le_bytes());
+	}
+
+	pub fn num(&mut self, num: usize) {
+		self.word(num as u32);
+	}
+
+	pub fn string(&mut self, string: &str) {
+		self.blob(string.as_bytes());
+	}
+
+	pub fn blob(&mut self, bytes: &[u8]) {
+		self.num(bytes.len());
+		self.bytes.extend_from_slice(bytes);
+	}
+}
+
+pub struct Reader<'a> {
+	pub bytes: &'a [u8],
+	pub pos: usize
+}
+
+impl<'a> Reader<'a> {
+	pub fn word(&mut self) -> Option<u32> {
+		let bytes = self.bytes.get(self.pos..self.pos + 4)?;
+		self.pos += 4;
+
+		Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+	}
+
+	pub fn num(&mut self) -> Option<usize> {
+		self.word().map(|word| word as usize)
+	}
+
+	pub fn string(&mut self) -> Option<String> {
+		String::from_utf8(self.blob()?.to_vec()).ok()
+	}
+
+	pub fn blob(&mut self) -> Option<&'a [u8]> {
+		let len = self.num()?;
+		let bytes = self.bytes.get(self.pos..self.pos + len)?;
+		self.pos += len;
+
+		Some(bytes)
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use assembler::Assembler;
+
+	fn sample() -> Object {
+		let mut assembler = Assembler::new();
+		assembler.object = true;
+
+		let program = assembler
+			.assemble("-", String::from(".global main\n.extern f\nmain\n\tcall f\n.section data\nvalue\n\t(main)\n"))
+			.unwrap();
+
+		Object::new(&program)
+	}
+
+	#[test]
+	fn round_trip() {
+		let bytes = sample().to_bytes();
+		let object = Object::from_bytes(&bytes).unwrap();
+
+		assert_eq!(object.to_bytes(), bytes);
+		assert_eq!(object.sections.len(), 2);
+		assert_eq!(object.sections[1].name, "data");
+		assert_eq!(object.find("main").map(|symbol| symbol.global), Some(true));
+		assert_eq!(object.find("value").map(|symbol| (symbol.section, symbol.global)), Some((1, false)));
+		assert_eq!(object.imports, ["f"]);
+		assert_eq!(object.relocs.len(), 2);
+	}
+
+	#[test]
+	fn truncated() {
+		let bytes = sample().to_bytes();
+
+		for len in 0..bytes.len() {
+			assert!(Object::from_bytes(&bytes[..len]).is_none());
+		}
+	}
+
+	#[test]
+	fn corrupt() {
+		let bytes = sample().to_bytes();
+
+		let mut magic = bytes.clone();
+		magic[0] = b'X';
+		assert!(Object::from_bytes(&magic).is_none());
+
+		let mut version = bytes.clone();
+		version[4] = 3;
+		assert!(Object::from_bytes(&version).is_none());
+
+		let mut trailing = bytes.clone();
+		trailing.push(0);
+		assert!(Object::from_bytes(&trailing).is_none());
+
+		// a symbol in a section that does not exist
+		let mut symbol = sample();
+		symbol.symbols[0].section = 2;
+		assert!(Object::from_bytes(&symbol.to_bytes()).is_none());
+
+		// a reloc past the end of its section
+		let mut reloc = sample();
+		reloc.relocs[0].addr = 100;
+		assert!(Object::from_bytes(&reloc.to_bytes()).is_none());
+	}
+}
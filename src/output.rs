@@ -0,0 +1,583 @@
+// most zero words a flat format fills in between two segments
+pub const MAX_GAP: usize = 0x10000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Endian {
+	Little,
+	Big
+}
+
+impl Endian {
+	pub fn from_name(name: &str) -> Option<Endian> {
+		match name {
+			"little" | "le" => Some(Endian::Little),
+			"big" | "be" => Some(Endian::Big),
+			_ => None
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Format {
+	// binary words
+	Raw,
+	// one hexadecimal word per line
+	Text,
+	// Intel HEX
+	Ihex,
+	// Motorola S-record
+	Srec,
+	// Verilog `$readmemh` / `$readmemb`
+	Readmemh,
+	Readmemb,
+	// Xilinx memory initialization
+	Coe,
+	// Intel/Altera memory initialization
+	Mif
+}
+
+impl Format {
+	pub fn from_name(name: &str) -> Option<Format> {
+		match name {
+			"raw" | "bin" => Some(Format::Raw),
+			"text" | "txt" => Some(Format::Text),
+			"ihex" | "hex" => Some(Format::Ihex),
+			"srec" => Some(Format::Srec),
+			"readmemh" | "memh" => Some(Format::Readmemh),
+			"readmemb" | "memb" => Some(Format::Readmemb),
+			"coe" => Some(Format::Coe),
+			"mif" => Some(Format::Mif),
+			_ => None
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OutputError {
+	// memory words, depth
+	Depth(usize, usize),
+	// zero words between two segments of a flat format
+	Gap(usize)
+}
+
+impl OutputError {
+	pub fn id(&self) -> &'static str {
+		match *self {
+			OutputError::Depth(..) => "Depth",
+			OutputError::Gap(..) => "Gap"
+		}
+	}
+
+	pub fn message(&self) -> String {
+		match *self {
+			OutputError::Depth(words, depth) =>
+				format!("image of {} memory words does not fit in depth {}", words, depth),
+			OutputError::Gap(words) =>
+				format!("{} word gap between sections is too large for a flat format, use ihex or srec", words)
+		}
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct Options {
+	pub format: Format,
+	// byte order of words, also the order of narrower memory words
+	pub endian: Endian,
+	// byte address of the first word
+	pub base: u32,
+	// data bytes per record
+	pub record_len: usize,
+	// bits per memory word, 8, 16 or 32
+	pub width: usize,
+	// memory words to pad to
+	pub depth: Option<usize>
+}
+
+impl Default for Options {
+	fn default() -> Options {
+		Options::new()
+	}
+}
+
+impl Options {
+	pub fn new() -> Options {
+		Options {
+			format: Format::Raw,
+			endian: Endian::Little,
+			base: 0,
+			record_len: 16,
+			width: 32,
+			depth: None
+		}
+	}
+}
+
+pub fn word_bytes(word: u32, endian: &Endian) -> [u8; 4] {
+	match *endian {
+		Endian::Little => word.to_le_bytes(),
+		Endian::Big => word.to_be_bytes()
+	}
+}
+
+pub fn bytes(binary: &[u32], endian: &Endian) -> Vec<u8> {
+	let mut bytes = Vec::with_capacity(binary.len() * 4);
+
+	for word in binary {
+		bytes.extend_from_slice(&word_bytes(*word, endian));
+	}
+
+	bytes
+}
+
+// raw binary back into words, `None` if it is not a whole number of words
+pub fn words(bytes: &[u8], endian: &Endian) -> Option<Vec<u32>> {
+	if !bytes.len().is_multiple_of(4) {
+		return None
+	}
+
+	Some(bytes
+		.chunks(4)
+		.map(|word| {
+			let word = [word[0], word[1], word[2], word[3]];
+
+			match *endian {
+				Endian::Little => u32::from_le_bytes(word),
+				Endian::Big => u32::from_be_bytes(word)
+			}
+		})
+		.collect())
+}
+
+fn hex(bytes: &[u8]) -> String {
+	bytes.iter().map(|byte| format!("{:02X}", byte)).collect()
+}
+
+// `:LLAAAATT[DD...]CC`, checksum is the two's complement of the byte sum
+fn ihex_record(addr: u16, kind: u8, data: &[u8]) -> String {
+	let mut record = vec![data.len() as u8, (addr >> 8) as u8, addr as u8, kind];
+	record.extend_from_slice(data);
+
+	let sum = record.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+	record.push(sum.wrapping_neg());
+
+	format!(":{}\n", hex(&record))
+}
+
+// `segments` are byte addresses and their data, nothing is written between them
+pub fn ihex(segments: &[(u32, Vec<u8>)], record_len: usize) -> String {
+	let mut out = String::new();
+	let mut upper = 0;
+
+	for (base, data) in segments {
+		let mut offset = 0;
+
+		while offset < data.len() {
+			let addr = base.wrapping_add(offset as u32);
+
+			// extended linear address record for the upper 16 bits
+			if addr >> 16 != upper {
+				upper = addr >> 16;
+				out.push_str(&ihex_record(0, 4, &[(upper >> 8) as u8, upper as u8]));
+			}
+
+			// records never cross a 64K boundary
+			let len = record_len
+				.min(data.len() - offset)
+				.min(0x10000 - (addr & 0xffff) as usize);
+
+			out.push_str(&ihex_record(addr as u16, 0, &data[offset..offset + len]));
+			offset += len;
+		}
+	}
+
+	out.push_str(&ihex_record(0, 1, &[]));
+	out
+}
+
+// `STLL[AA...][DD...]CC`, checksum is the ones' complement of the byte sum
+fn srec_record(kind: u8, addr: u32, addr_len: usize, data: &[u8]) -> String {
+	let mut record = vec![(addr_len + data.len() + 1) as u8];
+
+	for i in (0..addr_len).rev() {
+		record.push((addr >> (i * 8)) as u8);
+	}
+
+	record.extend_from_slice(data);
+
+	let sum = record.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+	record.push(!sum);
+
+	format!("S{}{}\n", kind, hex(&record))
+}
+
+// `segments` are byte addresses and their data, the start address is the first one
+pub fn srec(segments: &[(u32, Vec<u8>)], record_len: usize) -> String {
+	let end = segments
+		.iter()
+		.map(|(base, data)| *base as u64 + data.len() as u64)
+		.max()
+		.unwrap_or(0);
+
+	// S1/S9 for 16 bit, S2/S8 for 24 bit and S3/S7 for 32 bit addresses
+	let (kind, term, addr_len) = if end <= 0x10000 {
+		(1, 9, 2)
+	} else if end <= 0x1000000 {
+		(2, 8, 3)
+	} else {
+		(3, 7, 4)
+	};
+
+	let mut out = srec_record(0, 0, 2, b"jcpu-asm");
+	let mut count = 0;
+
+	for (base, data) in segments {
+		for (i, chunk) in data.chunks(record_len).enumerate() {
+			let addr = base.wrapping_add((i * record_len) as u32);
+
+			out.push_str(&srec_record(kind, addr, addr_len, chunk));
+			count += 1;
+		}
+	}
+
+	// record count, only when it fits in 16 bits
+	if count <= 0xffff {
+		out.push_str(&srec_record(5, count, 2, &[]));
+	}
+
+	let start = segments.first().map(|segment| segment.0).unwrap_or(0);
+
+	out.push_str(&srec_record(term, start, addr_len, &[]));
+	out
+}
+
+pub fn valid_width(width: usize) -> bool {
+	width == 8 || width == 16 || width == 32
+}
+
+// splits every word into `width` bit memory words
+// low part first for little endian, high part first for big endian
+pub fn mem_words(binary: &[u32], width: usize, endian: &Endian) -> Vec<u32> {
+	let parts = 32 / width;
+	let mask = if width == 32 { !0 } else { (1 << width) - 1 };
+	let mut words = Vec::with_capacity(binary.len() * parts);
+
+	for word in binary {
+		for i in 0..parts {
+			let part = match *endian {
+				Endian::Little => i,
+				Endian::Big => parts - 1 - i
+			};
+
+			words.push((word >> (part * width)) & mask);
+		}
+	}
+
+	words
+}
+
+fn text(binary: &[u32]) -> String {
+	binary.iter().map(|word| format!("{:08x}\n", word)).collect()
+}
+
+fn readmem(words: &[u32], width: usize, depth: usize, radix: u32) -> String {
+	let mut out = String::from("// generated by jcpu-asm\n");
+
+	for i in 0..depth {
+		let word = words.get(i).cloned().unwrap_or(0);
+
+		match radix {
+			2 => out.push_str(&format!("{:01$b}\n", word, width)),
+			_ => out.push_str(&format!("{:01$x}\n", word, width / 4))
+		}
+	}
+
+	out
+}
+
+// an empty image is one zero word, tools reject an empty vector
+fn coe(words: &[u32], width: usize, depth: usize) -> String {
+	let depth = depth.max(1);
+	let mut out = String::from("memory_initialization_radix=16;\nmemory_initialization_vector=\n");
+
+	for i in 0..depth {
+		let word = words.get(i).cloned().unwrap_or(0);
+		let end = if i + 1 == depth { ';' } else { ',' };
+
+		out.push_str(&format!("{:01$x}{2}\n", word, width / 4, end));
+	}
+
+	out
+}
+
+// an empty image is one zero word, `DEPTH` must be positive
+fn mif(words: &[u32], width: usize, depth: usize) -> String {
+	let depth = depth.max(1);
+	let mut out = format!(
+		"WIDTH={};\nDEPTH={};\n\nADDRESS_RADIX=HEX;\nDATA_RADIX=HEX;\n\nCONTENT BEGIN\n",
+		width,
+		depth
+	);
+
+	for (i, word) in words.iter().enumerate() {
+		out.push_str(&format!("\t{:x} : {:02$x};\n", i, word, width / 4));
+	}
+
+	// padding as a single range
+	if words.len() + 1 == depth {
+		out.push_str(&format!("\t{:x} : {:02$x};\n", words.len(), 0, width / 4));
+	} else if words.len() < depth {
+		out.push_str(&format!("\t[{:x}..{:x}] : {:03$x};\n", words.len(), depth - 1, 0, width / 4));
+	}
+
+	out.push_str("END;\n");
+	out
+}
+
+// largest `record_len` accepted by `format`
+pub fn max_record_len(format: &Format) -> usize {
+	match *format {
+		Format::Ihex => 255,
+		// count byte covers the address and checksum
+		Format::Srec => 255 - 4 - 1,
+		_ => usize::MAX
+	}
+}
+
+// one image from the lowest segment up, gaps are filled with zeros
+// segments may not overlap
+fn flatten(segments: &[(usize, Vec<u32>)]) -> Result<Vec<u32>, OutputError> {
+	let mut segments: Vec<&(usize, Vec<u32>)> = segments.iter().filter(|segment| !segment.1.is_empty()).collect();
+	segments.sort_by_key(|segment| segment.0);
+
+	let start = segments.first().map(|segment| segment.0).unwrap_or(0);
+	let mut binary = vec![];
+
+	for (addr, words) in segments {
+		let gap = addr - start - binary.len();
+
+		if gap > MAX_GAP {
+			return Err(OutputError::Gap(gap))
+		}
+
+		binary.resize(binary.len() + gap, 0);
+		binary.extend_from_slice(words);
+	}
+
+	Ok(binary)
+}
+
+// byte address and bytes of every non-empty segment, words are 4 bytes from `options.base`
+fn byte_segments(segments: &[(usize, Vec<u32>)], options: &Options) -> Vec<(u32, Vec<u8>)> {
+	segments
+		.iter()
+		.filter(|segment| !segment.1.is_empty())
+		.map(|(addr, words)| (options.base.wrapping_add(*addr as u32 * 4), bytes(words, &options.endian)))
+		.collect()
+}
+
+// `binary` is one image without gaps
+fn flat_output(binary: &[u32], options: &Options) -> Result<Vec<u8>, OutputError> {
+	let words = mem_words(binary, options.width, &options.endian);
+	let depth = options.depth.unwrap_or(words.len());
+
+	if words.len() > depth {
+		return Err(OutputError::Depth(words.len(), depth))
+	}
+
+	let out = match options.format {
+		Format::Raw => return Ok(bytes(binary, &options.endian)),
+		Format::Text => text(binary),
+		Format::Readmemh => readmem(&words, options.width, depth, 16),
+		Format::Readmemb => readmem(&words, options.width, depth, 2),
+		Format::Coe => coe(&words, options.width, depth),
+		Format::Mif => mif(&words, options.width, depth),
+		// written by `output` without flattening
+		Format::Ihex | Format::Srec => unreachable!()
+	};
+
+	Ok(out.into_bytes())
+}
+
+// `segments` are word addresses and their words
+// ihex and srec write only the segments, the other formats start at the lowest one
+pub fn output(segments: &[(usize, Vec<u32>)], options: &Options) -> Result<Vec<u8>, OutputError> {
+	let out = match options.format {
+		Format::Ihex => ihex(&byte_segments(segments, options), options.record_len),
+		Format::Srec => srec(&byte_segments(segments, options), options.record_len),
+		_ => return flat_output(&flatten(segments)?, options)
+	};
+
+	Ok(out.into_bytes())
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn ihex_known_record() {
+		assert_eq!(ihex(&[(0x10, b"address gap".to_vec())], 16), ":0B0010006164647265737320676170A7\n:00000001FF\n");
+	}
+
+	#[test]
+	fn ihex_odd_record_len() {
+		assert_eq!(
+			ihex(&[(0x100, vec![1, 2, 3, 4, 5])], 3),
+			":03010000010203F6\n:020103000405F1\n:00000001FF\n"
+		);
+	}
+
+	#[test]
+	fn ihex_splits_at_64k() {
+		assert_eq!(
+			ihex(&[(0xfffe, vec![0xaa, 0xbb, 0xcc, 0xdd])], 16),
+			":02FFFE00AABB9C\n:020000040001F9\n:02000000CCDD55\n:00000001FF\n"
+		);
+	}
+
+	#[test]
+	fn ihex_skips_gaps() {
+		let options = Options {
+			format: Format::Ihex,
+			..Options::new()
+		};
+
+		assert_eq!(
+			output(&[(0, vec![1]), (0x1000000, vec![2])], &options).unwrap(),
+			b":0400000001000000FB\n:020000040400F6\n:0400000002000000FA\n:00000001FF\n".to_vec()
+		);
+	}
+
+	#[test]
+	fn flat_formats_fill_gaps() {
+		let options = Options::new();
+
+		assert_eq!(output(&[(4, vec![1]), (6, vec![2])], &options).unwrap(), [1, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0]);
+		assert_eq!(output(&[(0, vec![1]), (MAX_GAP + 2, vec![2])], &options), Err(OutputError::Gap(MAX_GAP + 1)));
+	}
+
+	#[test]
+	fn srec_known_record() {
+		let data = [
+			0x7c, 0x08, 0x02, 0xa6, 0x90, 0x01, 0x00, 0x04, 0x94, 0x21, 0xff, 0xf0, 0x7c, 0x6c,
+			0x1b, 0x78, 0x7c, 0x8c, 0x23, 0x78, 0x3c, 0x60, 0x00, 0x00, 0x38, 0x63, 0x00, 0x00
+		];
+
+		assert_eq!(
+			srec(&[(0, data.to_vec())], 28),
+			"S00B00006A6370752D61736DD4\n\
+			S11F00007C0802A6900100049421FFF07C6C1B787C8C23783C6000003863000026\n\
+			S5030001FB\n\
+			S9030000FC\n"
+		);
+	}
+
+	#[test]
+	fn srec_base_and_odd_record_len() {
+		assert_eq!(
+			srec(&[(0x10000, vec![1, 2, 3])], 2),
+			"S00B00006A6370752D61736DD4\n\
+			S2060100000102F5\n\
+			S20501000203F4\n\
+			S5030002FA\n\
+			S804010000FA\n"
+		);
+	}
+
+	fn mem_output(format: Format, binary: &[u32], depth: Option<usize>) -> String {
+		let options = Options {
+			format,
+			width: 16,
+			depth,
+			..Options::new()
+		};
+
+		String::from_utf8(output(&[(0, binary.to_vec())], &options).unwrap()).unwrap()
+	}
+
+	#[test]
+	fn readmem_golden() {
+		assert_eq!(mem_output(Format::Readmemh, &[0x12345678], Some(3)), "// generated by jcpu-asm\n5678\n1234\n0000\n");
+		assert_eq!(
+			mem_output(Format::Readmemb, &[0x80010002], None),
+			"// generated by jcpu-asm\n0000000000000010\n1000000000000001\n"
+		);
+	}
+
+	#[test]
+	fn coe_golden() {
+		assert_eq!(
+			mem_output(Format::Coe, &[0x12345678], Some(3)),
+			"memory_initialization_radix=16;\nmemory_initialization_vector=\n5678,\n1234,\n0000;\n"
+		);
+	}
+
+	#[test]
+	fn mif_golden() {
+		assert_eq!(
+			mem_output(Format::Mif, &[0x12345678], Some(5)),
+			"WIDTH=16;\nDEPTH=5;\n\nADDRESS_RADIX=HEX;\nDATA_RADIX=HEX;\n\nCONTENT BEGIN\n\
+			\t0 : 5678;\n\t1 : 1234;\n\t[2..4] : 0000;\nEND;\n"
+		);
+		assert_eq!(
+			mem_output(Format::Mif, &[0x12345678], Some(3)),
+			"WIDTH=16;\nDEPTH=3;\n\nADDRESS_RADIX=HEX;\nDATA_RADIX=HEX;\n\nCONTENT BEGIN\n\
+			\t0 : 5678;\n\t1 : 1234;\n\t2 : 0000;\nEND;\n"
+		);
+	}
+
+	#[test]
+	fn empty_memory_image() {
+		assert_eq!(mem_output(Format::Coe, &[], None), "memory_initialization_radix=16;\nmemory_initialization_vector=\n0000;\n");
+		assert_eq!(
+			mem_output(Format::Mif, &[], None),
+			"WIDTH=16;\nDEPTH=1;\n\nADDRESS_RADIX=HEX;\nDATA_RADIX=HEX;\n\nCONTENT BEGIN\n\t0 : 0000;\nEND;\n"
+		);
+	}
+
+	#[test]
+	fn both_byte_orders() {
+		let image = [(0, vec![0x11223344, 0xaabbccdd])];
+		let little = Options::new();
+		let big = Options {
+			endian: Endian::Big,
+			..Options::new()
+		};
+
+		assert_eq!(output(&image, &little).unwrap(), [0x44, 0x33, 0x22, 0x11, 0xdd, 0xcc, 0xbb, 0xaa]);
+		assert_eq!(output(&image, &big).unwrap(), [0x11, 0x22, 0x33, 0x44, 0xaa, 0xbb, 0xcc, 0xdd]);
+		assert_eq!(words(&output(&image, &big).unwrap(), &Endian::Big).unwrap(), image[0].1);
+
+		let ihex_little = Options {
+			format: Format::Ihex,
+			..little
+		};
+		let ihex_big = Options {
+			format: Format::Ihex,
+			..big
+		};
+
+		assert_eq!(output(&image, &ihex_little).unwrap(), b":0800000044332211DDCCBBAA40\n:00000001FF\n".to_vec());
+		assert_eq!(output(&image, &ihex_big).unwrap(), b":0800000011223344AABBCCDD40\n:00000001FF\n".to_vec());
+
+		assert_eq!(mem_words(&image[0].1, 8, &Endian::Little), [0x44, 0x33, 0x22, 0x11, 0xdd, 0xcc, 0xbb, 0xaa]);
+		assert_eq!(mem_words(&image[0].1, 16, &Endian::Big), [0x1122, 0x3344, 0xaabb, 0xccdd]);
+	}
+
+	// one word per line, the byte order does not change it
+	#[test]
+	fn text_word_per_line() {
+		let image = [(0, vec![0x11223344, 0xa])];
+
+		for endian in [Endian::Little, Endian::Big] {
+			let options = Options {
+				format: Format::Text,
+				endian,
+				..Options::new()
+			};
+
+			assert_eq!(output(&image, &options).unwrap(), b"11223344\n0000000a\n".to_vec());
+		}
+	}
+}
@@ -1,21 +1,81 @@
-use std::collections::HashMap;
+use std::fs;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
 
+use loc::Loc;
 use token::{TokenId, Token};
 use lexer::Lexer;
 use error::{ErrorId, Error, gen_error};
 use node::{Program, Node};
+use source::Files;
+
+
+// statements that are not `to`, they cannot be data words naming a label
+const PSEUDOS: [&str; 7] = ["jmp", "call", "ret", "nop", "halt", "push", "pop"];
+
+// precedence of a binary operator, higher binds tighter
+// the levels follow Rust, comparisons bind loosest
+fn binary_prec(id: &TokenId) -> Option<u8> {
+	match *id {
+		TokenId::Eql | TokenId::Lt | TokenId::Gt => Some(1),
+		TokenId::Or | TokenId::Nor => Some(2),
+		TokenId::Xor | TokenId::Xnor => Some(3),
+		TokenId::And | TokenId::Nand => Some(4),
+		TokenId::Sl | TokenId::Sr => Some(5),
+		TokenId::Add | TokenId::Sub => Some(6),
+		TokenId::Mul | TokenId::Div => Some(7),
+		_ => None
+	}
+}
 
+fn binary_node(id: TokenId, left: Node, right: Node) -> Node {
+	let (left, right) = (Box::new(left), Box::new(right));
+
+	match id {
+		TokenId::Eql => Node::Eql(left, right),
+		TokenId::Lt => Node::Lt(left, right),
+		// x > y is y < x
+		TokenId::Gt => Node::Lt(right, left),
+		TokenId::Or => Node::Or(left, right),
+		TokenId::Nor => Node::Not(Box::new(Node::Or(left, right))),
+		TokenId::Xor => Node::Xor(left, right),
+		TokenId::Xnor => Node::Not(Box::new(Node::Xor(left, right))),
+		TokenId::And => Node::And(left, right),
+		TokenId::Nand => Node::Not(Box::new(Node::And(left, right))),
+		TokenId::Sl => Node::Sl(left, right),
+		TokenId::Sr => Node::Sr(left, right),
+		TokenId::Add => Node::Add(left, right),
+		TokenId::Sub => Node::Sub(left, right),
+		TokenId::Mul => Node::Mul(left, right),
+		TokenId::Div => Node::Div(left, right),
+		_ => unreachable!()
+	}
+}
 
-struct Parser {
+struct Parser<'a> {
 	lexer: Lexer,
-	token_result: Result<Token, Error>
+	token_result: Result<Token, Error>,
+	files: &'a mut Files,
+	include_dirs: &'a [PathBuf],
+	// files being parsed, innermost last
+	chain: Vec<PathBuf>,
+	// labels defined so far, `.reg` may not shadow them
+	labels: HashSet<String>
 }
 
-impl Parser {
+impl<'a> Parser<'a> {
 	fn advance(&mut self) {
 		self.token_result = self.lexer.token();
 	}
 
+	// location of the current token
+	fn loc(&self) -> Loc {
+		match self.token_result {
+			Err(ref err) => err.loc.clone(),
+			Ok(ref token) => token.loc.clone()
+		}
+	}
+
 	fn parse_paren(&mut self) -> Result<Node, Error> {
 		match self.token_result.clone() {
 			Err(err) => Err(err),
@@ -66,7 +126,7 @@ impl Parser {
 					Ok(node)
 				},
 				// parse register
-				TokenId::Reg(reg) => {
+				TokenId::Reg(reg) | TokenId::Alias(_, reg) => {
 					let node = Node::Reg(reg);
 
 					self.advance();
@@ -141,130 +201,30 @@ impl Parser {
 		}
 	}
 
-	fn parse_oper(&mut self) -> Result<Node, Error> {
-		let node_result = self.parse_atom();
+	// parse binary operators binding at least as tightly as `min_prec`
+	// x + y * z is x + (y * z), x - y - z is (x - y) - z
+	fn parse_binary(&mut self, min_prec: u8) -> Result<Node, Error> {
+		let mut node = self.parse_atom()?;
 
-		match node_result {
-			Err(_) => node_result,
-			Ok(mut node) => loop {
-				match self.token_result.clone() {
-					Err(err) => return Err(err),
-					Ok(token) => match token.id {
-						TokenId::Or => {
-							self.advance();
-
-							let right_result = self.parse_atom();
-							match right_result {
-								Err(_) => return right_result,
-								Ok(right) => node = Node::Or(Box::new(node), Box::new(right))
-							}
-						},
-						TokenId::And => {
-							self.advance();
-
-							let right_result = self.parse_atom();
-							match right_result {
-								Err(_) => return right_result,
-								Ok(right) => node = Node::And(Box::new(node), Box::new(right))
-							}
-						},
-						TokenId::Xor => {
-							self.advance();
-
-							let right_result = self.parse_atom();
-							match right_result {
-								Err(_) => return right_result,
-								Ok(right) => node = Node::Xor(Box::new(node), Box::new(right))
-							}
-						},
-						TokenId::Add => {
-							self.advance();
-
-							let right_result = self.parse_atom();
-							match right_result {
-								Err(_) => return right_result,
-								Ok(right) => node = Node::Add(Box::new(node), Box::new(right))
-							}
-						},
-						TokenId::Sub => {
-							self.advance();
-
-							let right_result = self.parse_atom();
-							match right_result {
-								Err(_) => return right_result,
-								Ok(right) => node = Node::Sub(Box::new(node), Box::new(right))
-							}
-						},
-						TokenId::Sl => {
-							self.advance();
-
-							let right_result = self.parse_atom();
-							match right_result {
-								Err(_) => return right_result,
-								Ok(right) => node = Node::Sl(Box::new(node), Box::new(right))
-							}
-						},
-						TokenId::Sr => {
-							self.advance();
-
-							let right_result = self.parse_atom();
-							match right_result {
-								Err(_) => return right_result,
-								Ok(right) => node = Node::Sr(Box::new(node), Box::new(right))
-							}
-						},
-						TokenId::Mul => {
-							self.advance();
-
-							let right_result = self.parse_atom();
-							match right_result {
-								Err(_) => return right_result,
-								Ok(right) => node = Node::Mul(Box::new(node), Box::new(right))
-							}
-						},
-						TokenId::Div => {
-							self.advance();
-
-							let right_result = self.parse_atom();
-							match right_result {
-								Err(_) => return right_result,
-								Ok(right) => node = Node::Div(Box::new(node), Box::new(right))
-							}
-						},
-						TokenId::Eql => {
-							self.advance();
-
-							let right_result = self.parse_atom();
-							match right_result {
-								Err(_) => return right_result,
-								Ok(right) => node = Node::Eql(Box::new(node), Box::new(right))
-							}
-						},
-						TokenId::Gt => {
-							self.advance();
-
-							let right_result = self.parse_atom();
-							match right_result {
-								Err(_) => return right_result,
-								Ok(right) => node = Node::Lt(Box::new(right), Box::new(node))
-							}
-						},
-						TokenId::Lt => {
-							self.advance();
-
-							let right_result = self.parse_atom();
-							match right_result {
-								Err(_) => return right_result,
-								Ok(right) => node = Node::Lt(Box::new(node), Box::new(right))
-							}
-						},
-						_ => return Ok(node)
-					}
-				}
-			}
+		loop {
+			let token = self.token_result.clone()?;
+
+			let prec = match binary_prec(&token.id) {
+				Some(prec) if prec >= min_prec => prec,
+				_ => return Ok(node)
+			};
+
+			self.advance();
+
+			let right = self.parse_binary(prec + 1)?;
+			node = binary_node(token.id, node, right);
 		}
 	}
 
+	fn parse_oper(&mut self) -> Result<Node, Error> {
+		self.parse_binary(1)
+	}
+
 	// parse operands
 	// x, y, z, ..., w
 	fn parse_opers(&mut self) -> Result<Node, Error> {
@@ -288,32 +248,67 @@ impl Parser {
 								nodes.push(node);
 
 								loop {
-									match self.token_result.clone() {
-										Err(err) => return Err(err),
-										Ok(token) => match token.id {
-											TokenId::Comma => {
-												self.advance();
-
-												let node_result = self.parse_oper();
-
-												match node_result {
-													Err(err) => return Err(err),
-													Ok(node) => nodes.push(node)
-												}
-											},
-											_ => return Ok(Node::Opers(nodes))
-										}
+									match self.token_result.clone()?.id {
+										TokenId::Comma => {
+											self.advance();
+
+											nodes.push(self.parse_oper()?);
+										},
+										_ => return Ok(Node::Opers(nodes))
 									}
 								}
 							}
 						}
 					},
-					_ => return Ok(node)
+					_ => Ok(node)
 				}
 			}
 		}
 	}
 
+	// parse `? cond` after `node`
+	fn parse_cond(&mut self, node: Node) -> Result<Node, Error> {
+		match self.token_result.clone()?.id {
+			TokenId::If => {
+				self.advance();
+
+				let cond = self.parse_oper()?;
+
+				Ok(Node::Cond(Box::new(node), Box::new(cond)))
+			},
+			_ => Ok(node)
+		}
+	}
+
+	// parse `x, y, z`
+	fn parse_oper_list(&mut self) -> Result<Vec<Node>, Error> {
+		let mut nodes = vec![self.parse_oper()?];
+
+		while self.token_result.clone()?.id == TokenId::Comma {
+			self.advance();
+
+			nodes.push(self.parse_oper()?);
+		}
+
+		Ok(nodes)
+	}
+
+	// parse `jmp x`, `call x`, `ret`, `nop`, `halt`, `push x, y` and `pop x, y`
+	// `name` has been read
+	fn parse_pseudo(&mut self, name: &str) -> Result<Node, Error> {
+		let node = match name {
+			"jmp" => Node::Jump(Box::new(self.parse_oper()?)),
+			"call" => Node::Call(Box::new(self.parse_oper()?)),
+			"ret" => Node::Ret,
+			"nop" => Node::Nop,
+			"push" => Node::Push(self.parse_oper_list()?),
+			"pop" => Node::Pop(self.parse_oper_list()?),
+			_ => Node::Halt
+		};
+
+		self.parse_cond(node)
+	}
+
 	fn parse_to(&mut self) -> Result<Node, Error> {
 		let left_result = self.parse_opers();
 
@@ -331,15 +326,7 @@ impl Parser {
 							Ok(right) => match self.token_result.clone() {
 								Err(err) => Err(err),
 								Ok(token) => match token.id {
-									TokenId::If => {
-										self.advance();
-
-										let cond_result = self.parse_oper();
-										match cond_result {
-											Err(err) => Err(err),
-											Ok(cond) => Ok(Node::Cond(Box::new(Node::To(Box::new(left), Box::new(right))), Box::new(cond)))
-										}
-									},
+									TokenId::If => self.parse_cond(Node::To(Box::new(left), Box::new(right))),
 									_ => Ok(Node::To(Box::new(left), Box::new(right)))
 								}
 							}
@@ -351,77 +338,455 @@ impl Parser {
 		}
 	}
 
-	fn parse(&mut self) -> Result<Program, Error> {
-		let mut nodes = Vec::new();
+	fn parse_iden(&mut self) -> Result<String, Error> {
+		let token = self.token_result.clone()?;
+
+		match token.id {
+			TokenId::Iden(iden) => {
+				self.advance();
+
+				Ok(iden)
+			},
+			_ => gen_error(ErrorId::ExpectedIden, token)
+		}
+	}
+
+	// parse identifiers
+	// x, y, z, ..., w
+	fn parse_idens(&mut self) -> Result<Vec<String>, Error> {
+		let mut idens = vec![self.parse_iden()?];
+
+		while self.token_result.clone()?.id == TokenId::Comma {
+			self.advance();
+
+			idens.push(self.parse_iden()?);
+		}
+
+		Ok(idens)
+	}
+
+	fn parse_str(&mut self) -> Result<String, Error> {
+		let token = self.token_result.clone()?;
+
+		match token.id {
+			TokenId::Str(string) => {
+				self.advance();
+
+				Ok(string)
+			},
+			_ => gen_error(ErrorId::ExpectedStr, token)
+		}
+	}
+
+	// parse `.reg name, rN`, the alias applies from the next line
+	fn parse_reg_directive(&mut self) -> Result<Node, Error> {
+		let token = self.token_result.clone()?;
+
+		let name = match token.id {
+			TokenId::Iden(ref name) | TokenId::Alias(ref name, _) => name.clone(),
+			_ => return gen_error(ErrorId::ExpectedIden, token)
+		};
+
+		if self.labels.contains(&name) {
+			return gen_error(ErrorId::AliasShadowsLabel(name), token)
+		}
+
+		self.advance();
+
+		let token = self.token_result.clone()?;
+
+		if token.id != TokenId::Comma {
+			return gen_error(ErrorId::ExpectedReg, token)
+		}
+
+		self.advance();
+
+		let token = self.token_result.clone()?;
+
+		match token.id {
+			TokenId::Reg(reg) | TokenId::Alias(_, reg) => {
+				self.advance();
+				self.lexer.set_alias(name.clone(), reg);
+
+				Ok(Node::Alias(name, reg))
+			},
+			_ => gen_error(ErrorId::ExpectedReg, token)
+		}
+	}
+
+	// parse `.unreg name`, ending an alias
+	fn parse_unreg_directive(&mut self) -> Result<Node, Error> {
+		let token = self.token_result.clone()?;
+
+		match token.id {
+			TokenId::Alias(name, _) => {
+				self.advance();
+				self.lexer.remove_alias(&name);
+
+				Ok(Node::Unalias(name))
+			},
+			_ => gen_error(ErrorId::ExpectedReg, token)
+		}
+	}
+
+	// parse `.link rN`
+	fn parse_link_directive(&mut self) -> Result<Node, Error> {
+		let token = self.token_result.clone()?;
+
+		match token.id {
+			TokenId::Reg(reg) | TokenId::Alias(_, reg) => {
+				self.advance();
+
+				Ok(Node::Link(reg))
+			},
+			_ => gen_error(ErrorId::ExpectedReg, token)
+		}
+	}
+
+	// parse `.stack rN`
+	fn parse_stack_directive(&mut self) -> Result<Node, Error> {
+		let token = self.token_result.clone()?;
+
+		match token.id {
+			TokenId::Reg(reg) | TokenId::Alias(_, reg) => {
+				self.advance();
+
+				Ok(Node::Stack(reg))
+			},
+			_ => gen_error(ErrorId::ExpectedReg, token)
+		}
+	}
+
+	// parse `.scratch rN`, `.scratch _` leaves nested expressions without one
+	fn parse_scratch_directive(&mut self) -> Result<Node, Error> {
+		let token = self.token_result.clone()?;
+
+		let reg = match token.id {
+			TokenId::Reg(reg) | TokenId::Alias(_, reg) => Some(reg),
+			TokenId::Empty => None,
+			_ => return gen_error(ErrorId::ExpectedReg, token)
+		};
+
+		self.advance();
+
+		Ok(Node::Scratch(reg))
+	}
+
+	// parse `.section name`, `.global x, y`, `.extern x, y` and `.include "path"`
+	fn parse_directive(&mut self, directive: Token) -> Result<Node, Error> {
+		let name = match directive.id {
+			TokenId::Directive(ref name) => name.clone(),
+			_ => return gen_error(ErrorId::InvalidDirective, directive)
+		};
+
+		match name.as_ref() {
+			"section" => Ok(Node::Section(self.parse_iden()?)),
+			"include" => Ok(Node::Include(self.parse_str()?)),
+			"global" => Ok(Node::Global(self.parse_idens()?)),
+			"extern" => Ok(Node::Extern(self.parse_idens()?)),
+			"reg" => self.parse_reg_directive(),
+			"unreg" => self.parse_unreg_directive(),
+			"scratch" => self.parse_scratch_directive(),
+			"link" => self.parse_link_directive(),
+			"stack" => self.parse_stack_directive(),
+			_ => gen_error(ErrorId::InvalidDirective, directive)
+		}
+	}
+
+	// parse a directive or a `to`
+	fn parse_statement(&mut self) -> Result<Node, Error> {
+		let token = self.token_result.clone()?;
+
+		match token.id {
+			TokenId::Directive(_) => {
+				self.advance();
+
+				self.parse_directive(token)
+			},
+			TokenId::Iden(ref name) if PSEUDOS.contains(&name.as_ref()) => {
+				self.advance();
+
+				self.parse_pseudo(name)
+			},
+			_ => self.parse_to()
+		}
+	}
+
+	// parse the included file into `nodes`
+	// paths are relative to the including file, then to each include directory
+	fn include(&mut self, path: &str, loc: Loc, nodes: &mut Vec<Node>, locs: &mut Vec<Loc>) -> Result<(), Error> {
+		let dir = Path::new(&self.files.names[loc.file])
+			.parent()
+			.map(|dir| dir.to_path_buf())
+			.unwrap_or_default();
+
+		let candidates = Some(dir)
+			.into_iter()
+			.chain(self.include_dirs.iter().cloned())
+			.map(|dir| dir.join(path));
+
+		for candidate in candidates {
+			let source = match fs::read_to_string(&candidate) {
+				Err(_) => continue,
+				Ok(source) => source
+			};
+
+			let canonical = fs::canonicalize(&candidate).unwrap_or_else(|_| candidate.clone());
+
+			if self.chain.contains(&canonical) {
+				return Err(Error {
+					id: ErrorId::IncludeCycle,
+					loc
+				})
+			}
+
+			let file = self.files.add(candidate.to_string_lossy().into_owned(), source.clone());
+
+			let mut lexer = Lexer::new(source, file);
+			let token_result = lexer.token();
+
+			let mut chain = self.chain.clone();
+			chain.push(canonical);
+
+			let mut parser = Parser {
+				lexer,
+				token_result,
+				files: &mut *self.files,
+				include_dirs: self.include_dirs,
+				chain,
+				labels: HashSet::new()
+			};
 
+			return parser.parse(nodes, locs)
+		}
+
+		Err(Error {
+			id: ErrorId::IncludeNotFound,
+			loc
+		})
+	}
+
+	fn parse(&mut self, nodes: &mut Vec<Node>, locs: &mut Vec<Loc>) -> Result<(), Error> {
 		loop {
-			match self.token_result.clone() {
-				Err(err) => return Err(err),
-				Ok(token) => match token.id {
-					TokenId::Tab => {
+			let token = self.token_result.clone()?;
+
+			match token.id {
+				// directives may also start a line
+				TokenId::Tab | TokenId::Directive(_) => {
+					if token.id == TokenId::Tab {
 						self.advance();
+					}
 
-						let node_or_err = self.parse_to();
+					// statements separated by `;`, a trailing `;` is allowed
+					loop {
+						let loc = self.loc();
 
-						match node_or_err {
-							Err(err) => return Err(err),
-							Ok(node) => {
+						match self.parse_statement()? {
+							Node::Include(path) => self.include(&path, loc, nodes, locs)?,
+							node => {
 								nodes.push(node);
+								locs.push(loc);
 							}
 						}
 
-						match self.token_result.clone() {
-							Err(err) => return Err(err.clone()),
-							Ok(token) => match token.id {
-								TokenId::Line => self.advance(),
-								TokenId::Eof => break,
-								_ => return gen_error(ErrorId::ExpectedLine, token)
-							}
+						if self.token_result.clone()?.id != TokenId::Semi {
+							break
 						}
-					},
-					TokenId::Iden(iden) => {
-						// Parse label
-						nodes.push(Node::Label(iden.clone()));
 
 						self.advance();
 
-						match self.token_result.clone() {
-							Err(err) => return Err(err.clone()),
-							Ok(token) => match token.id {
-								TokenId::Line | TokenId::Eof => (),
-								_ => return gen_error(ErrorId::ExpectedLine, token)
-							}
+						match self.token_result.clone()?.id {
+							TokenId::Line | TokenId::Eof => break,
+							_ => ()
 						}
+					}
 
-						self.advance();
-					},
-					TokenId::Line => {
-						self.advance();
-					},
-					TokenId::Eof => break,
-					_ => return gen_error(ErrorId::ExpectedProgram, token.clone())
-				}
+					match self.token_result.clone() {
+						Err(err) => return Err(err.clone()),
+						Ok(token) => match token.id {
+							TokenId::Line => self.advance(),
+							TokenId::Eof => break,
+							_ => return gen_error(ErrorId::ExpectedLine, token)
+						}
+					}
+				},
+				TokenId::Alias(ref name, _) => return gen_error(ErrorId::AliasShadowsLabel(name.clone()), token.clone()),
+				TokenId::Iden(iden) => {
+					// Parse label
+					self.labels.insert(iden.clone());
+					nodes.push(Node::Label(iden.clone()));
+					locs.push(token.loc.clone());
+
+					self.advance();
+
+					match self.token_result.clone() {
+						Err(err) => return Err(err.clone()),
+						Ok(token) => match token.id {
+							TokenId::Line | TokenId::Eof => (),
+							_ => return gen_error(ErrorId::ExpectedLine, token)
+						}
+					}
+
+					self.advance();
+				},
+				TokenId::Line => {
+					self.advance();
+				},
+				TokenId::Eof => break,
+				_ => return gen_error(ErrorId::ExpectedProgram, token.clone())
 			}
 		}
 
-		Ok(Program {
-			nodes: nodes,
-			binary: Vec::new(),
-			labels: HashMap::new(),
-			queue: HashMap::new(),
-			addr: 0
-		})
+		Ok(())
 	}
 }
 
-pub fn parse(source: String) -> Result<Program, Error> {
-	let mut lexer = Lexer::new(source);
+// parses `file` of `files`, adding every included file to `files`
+pub fn parse_file(files: &mut Files, file: usize, include_dirs: &[PathBuf]) -> Result<Program, Error> {
+	let mut lexer = Lexer::new(files.sources[file].clone(), file);
 	let token_result = lexer.token();
 
+	let name = PathBuf::from(&files.names[file]);
+	let chain = vec![fs::canonicalize(&name).unwrap_or(name)];
+
 	let mut parser = Parser {
-		lexer: lexer,
-		token_result: token_result
+		lexer,
+		token_result,
+		files,
+		include_dirs,
+		chain,
+		labels: HashSet::new()
 	};
 
-	parser.parse()
-}
\ No newline at end of file
+	let mut nodes = Vec::new();
+	let mut locs = Vec::new();
+
+	parser.parse(&mut nodes, &mut locs)?;
+
+	Ok(Program::new(nodes, locs))
+}
+
+// parses a single source without includes
+pub fn parse(source: String) -> Result<Program, Error> {
+	let mut files = Files::new();
+	let file = files.add(String::from("-"), source);
+
+	parse_file(&mut files, file, &[])
+}
+
+#[cfg(test)]
+mod tests {
+	use super::parse;
+	use assembler::assemble;
+	use error::ErrorId;
+	use node::Node;
+
+	fn binary(source: &str) -> Vec<u32> {
+		assemble(source).unwrap().binary().to_vec()
+	}
+
+	// the parsed right side of `r0 -> expr`
+	fn tree(expr: &str) -> Node {
+		let mut program = parse(format!("\tr0 -> {}\n", expr)).unwrap();
+
+		match program.nodes.remove(0) {
+			Node::To(_, box right) => right,
+			node => panic!("expected a move, got {:?}", node)
+		}
+	}
+
+	fn reg(reg: u8) -> Box<Node> {
+		Box::new(Node::Reg(reg))
+	}
+
+	#[test]
+	fn semicolon_separates_statements() {
+		let lines = binary("\tr0 -> 1\n\tr1 -> 2\n\tr2 -> 3\n");
+
+		assert_eq!(binary("\tr0 -> 1 ; r1 -> 2\n\tr2 -> 3\n"), lines);
+		assert_eq!(binary("\tr0 -> 1; r1 -> 2;\n\tr2 -> 3\n"), lines);
+		assert_eq!(binary("\tr0 -> 1 ; r1 -> 2 # r3 -> 4\n\tr2 -> 3\n"), lines);
+	}
+
+	#[test]
+	fn return_address_idiom() {
+		let source = "\tr1 -> ret; r15 -> fn\nfn\n\thalt\nret\n\thalt\n";
+
+		assert_eq!(binary(source), binary("\tr1 -> ret\n\tr15 -> fn\nfn\n\thalt\nret\n\thalt\n"));
+	}
+
+	#[test]
+	fn semicolon_is_not_a_comment() {
+		assert!(assemble("; halt\n").is_err());
+	}
+
+	#[test]
+	fn mixed_precedence() {
+		// r1 | r2 ^ r3 & r4 << r5 + r6 * r7
+		let product = Node::Mul(reg(6), reg(7));
+		let sum = Node::Add(reg(5), Box::new(product));
+		let shift = Node::Sl(reg(4), Box::new(sum));
+		let and = Node::And(reg(3), Box::new(shift));
+		let xor = Node::Xor(reg(2), Box::new(and));
+
+		assert_eq!(tree("r1 | r2 ^ r3 & r4 << r5 + r6 * r7"), Node::Or(reg(1), Box::new(xor)));
+
+		// r1 * r2 + r3 = r4
+		let sum = Node::Add(Box::new(Node::Mul(reg(1), reg(2))), reg(3));
+
+		assert_eq!(tree("r1 * r2 + r3 = r4"), Node::Eql(Box::new(sum), reg(4)));
+	}
+
+	#[test]
+	fn left_associative() {
+		assert_eq!(tree("r1 - r2 - r3"), Node::Sub(Box::new(Node::Sub(reg(1), reg(2))), reg(3)));
+		assert_eq!(tree("r1 / r2 * r3"), Node::Mul(Box::new(Node::Div(reg(1), reg(2))), reg(3)));
+		assert_eq!(tree("r1 << r2 >> r3"), Node::Sr(Box::new(Node::Sl(reg(1), reg(2))), reg(3)));
+	}
+
+	#[test]
+	fn negated_operators() {
+		assert_eq!(tree("r1 !& r2"), Node::Not(Box::new(Node::And(reg(1), reg(2)))));
+		assert_eq!(tree("r1 !| r2"), Node::Not(Box::new(Node::Or(reg(1), reg(2)))));
+		assert_eq!(tree("r1 !^ r2"), Node::Not(Box::new(Node::Xor(reg(1), reg(2)))));
+
+		// same levels as `&`, `|` and `^`
+		let nand = Node::Not(Box::new(Node::And(reg(2), reg(3))));
+
+		assert_eq!(tree("r1 !| r2 !& r3"), Node::Not(Box::new(Node::Or(reg(1), Box::new(nand)))));
+		assert_eq!(
+			tree("r1 !^ r2 ^ r3"),
+			Node::Xor(Box::new(Node::Not(Box::new(Node::Xor(reg(1), reg(2))))), reg(3))
+		);
+	}
+
+	#[test]
+	fn alias_names_its_register() {
+		assert_eq!(binary(".reg count, r3\n\tcount -> count - 1\n"), binary("\tr3 -> r3 - 1\n"));
+		assert_eq!(binary(".reg count, r3\n.reg n, count\n\tn -> 1\n"), binary("\tr3 -> 1\n"));
+	}
+
+	#[test]
+	fn unreg_ends_alias() {
+		let errors = assemble(".reg count, r3\n\tcount -> 1\n.unreg count\n\tr0 -> count\n").unwrap_err();
+
+		assert_eq!(errors[0].id, ErrorId::UndefinedLabel(String::from("count")));
+		assert_eq!(errors[0].loc.line, 4);
+	}
+
+	#[test]
+	fn alias_shadows_label() {
+		for source in ["count\n.reg count, r3\n", ".reg count, r3\n\tr0 -> 1\ncount\n"] {
+			let errors = assemble(source).unwrap_err();
+
+			assert_eq!(errors[0].id, ErrorId::AliasShadowsLabel(String::from("count")));
+		}
+	}
+
+	#[test]
+	fn redefine_builtin_alias() {
+		assert_eq!(binary(".reg sp, r4\n\tsp -> 1\n"), binary("\tr4 -> 1\n"));
+		assert_eq!(binary(".unreg pc\n.reg pc, r2\n\tpc -> 1\n"), binary("\tr2 -> 1\n"));
+		assert_eq!(assemble(".unreg pc\n\tpc -> 1\n").unwrap_err()[0].id, ErrorId::InvalidInstruction);
+	}
+}
@@ -0,0 +1,130 @@
+use loc::Loc;
+use error::{ErrorId, Error};
+
+
+// memory range sections are placed in, in words
+#[derive(Debug, Clone)]
+pub struct Region {
+	pub name: String,
+	pub origin: usize,
+	pub len: usize
+}
+
+// memory layout used by the linker
+//
+// # comment
+// region NAME ORIGIN LENGTH
+// place SECTION REGION
+// entry LABEL
+//
+// sections are placed in the order of their `place` lines
+#[derive(Debug, Clone)]
+pub struct Script {
+	pub regions: Vec<Region>,
+	// section and region names
+	pub places: Vec<(String, String)>,
+	pub entry: Option<String>
+}
+
+// decimal or `0x` prefixed hexadecimal
+pub fn parse_num(string: &str) -> Option<u32> {
+	if string.starts_with("0x") || string.starts_with("0X") {
+		u32::from_str_radix(&string[2..], 16).ok()
+	} else {
+		string.parse().ok()
+	}
+}
+
+impl Default for Script {
+	fn default() -> Script {
+		Script::new()
+	}
+}
+
+impl Script {
+	// a single region starting at 0 holding every section
+	pub fn new() -> Script {
+		Script {
+			regions: vec![Region {
+				name: String::from("mem"),
+				origin: 0,
+				len: usize::MAX
+			}],
+			places: Vec::new(),
+			entry: None
+		}
+	}
+
+	pub fn parse(source: &str) -> Result<Script, Error> {
+		let mut script = Script {
+			regions: Vec::new(),
+			places: Vec::new(),
+			entry: None
+		};
+
+		for (i, line) in source.lines().enumerate() {
+			let error = Error {
+				id: ErrorId::InvalidScript,
+				loc: Loc {
+					pos: 0,
+					col: 1,
+					line: i + 1,
+					file: 0
+				}
+			};
+
+			let words: Vec<&str> = line
+				.split('#')
+				.next()
+				.unwrap_or("")
+				.split_whitespace()
+				.collect();
+
+			match words.as_slice() {
+				[] => (),
+				["region", name, origin, len] => match (parse_num(origin), parse_num(len)) {
+					(Some(origin), Some(len)) => script.regions.push(Region {
+						name: name.to_string(),
+						origin: origin as usize,
+						len: len as usize
+					}),
+					_ => return Err(error)
+				},
+				["place", section, region] => {
+					if !script.regions.iter().any(|r| r.name == *region) {
+						return Err(error)
+					}
+
+					script.places.push((section.to_string(), region.to_string()));
+				},
+				["entry", label] => script.entry = Some(label.to_string()),
+				_ => return Err(error)
+			}
+		}
+
+		if script.regions.is_empty() {
+			return Err(Error {
+				id: ErrorId::InvalidScript,
+				loc: Loc {
+					pos: 0,
+					col: 1,
+					line: 1,
+					file: 0
+				}
+			})
+		}
+
+		Ok(script)
+	}
+
+	// region of a section, the first region if nothing is placed
+	pub fn region(&self, section: &str) -> Option<usize> {
+		if self.places.is_empty() {
+			return Some(0)
+		}
+
+		let region = &self.places.iter().find(|place| place.0 == section)?.1;
+
+		self.regions.iter().position(|r| r.name == *region)
+	}
+}
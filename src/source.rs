@@ -0,0 +1,28 @@
+// source files read while parsing, indexed by `Loc::file`
+#[derive(Debug, Clone)]
+pub struct Files {
+	pub names: Vec<String>,
+	pub sources: Vec<String>
+}
+
+impl Default for Files {
+	fn default() -> Files {
+		Files::new()
+	}
+}
+
+impl Files {
+	pub fn new() -> Files {
+		Files {
+			names: Vec::new(),
+			sources: Vec::new()
+		}
+	}
+
+	pub fn add(&mut self, name: String, source: String) -> usize {
+		self.names.push(name);
+		self.sources.push(source);
+
+		self.names.len() - 1
+	}
+}
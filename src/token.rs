@@ -4,8 +4,12 @@ use loc::Loc;
 #[derive(Debug, Clone, PartialEq)]
 pub enum TokenId {
 	Iden(String),
+	Directive(String),
+	Str(String),
 	Num(isize),
 	Reg(u8),
+	// register alias and the register it names
+	Alias(String, u8),
 	Empty,
 
 	Mem8,
@@ -16,6 +20,10 @@ pub enum TokenId {
 	Or,
 	And,
 	Xor,
+	// `!|`, `!&` and `!^`
+	Nor,
+	Nand,
+	Xnor,
 	Add,
 	Sub,
 	Sl,
@@ -27,6 +35,8 @@ pub enum TokenId {
 	Gt,
 
 	Comma,
+	// separates statements on one line
+	Semi,
 
 	To,
 